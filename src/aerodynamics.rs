@@ -0,0 +1,412 @@
+use vector::Vector;
+use object::Object;
+use objectmanager::ObjectManager;
+use objectmanager::ObjectTag;
+use random::Random;
+
+const MIN_AIRSPEED   :f32 = 1f32;  //m/s, the turbulence filters get infinitely slow in calm air
+const FILTER_SUBSTEP :f32 = 0.05f32; //largest integration step of a shaping filter, relative to its time constant
+
+//drag coefficients of an object per body axis, all zero -> the air doesn't act on it
+#[derive(Clone, Copy)]
+pub struct Drag {
+    pub linear:    Vector, //N per m/s of airspeed, rotor drag and skin friction
+    pub quadratic: Vector, //N per (m/s)^2, form drag, 0.5 * air density * drag coefficient * area
+    pub angular:   Vector, //Nm per rad/s of body rate
+}
+
+#[allow(dead_code)]
+impl Drag {
+    pub fn new () -> Drag {
+        Drag {
+            linear:    Vector::null(),
+            quadratic: Vector::null(),
+            angular:   Vector::null(),
+        }
+    }
+
+    pub fn is_zero (&self) -> bool {
+        Vector::magnitude(self.linear) == 0f32 && Vector::magnitude(self.quadratic) == 0f32 && Vector::magnitude(self.angular) == 0f32
+    }
+
+    //world frame force and torque on an object moving through air that moves with wind
+    pub fn force_torque (&self, object: &Object, wind: Vector) -> (Vector, Vector) {
+        let local_to_world = object.rotation.to_matrix();
+        let world_to_local = local_to_world.transpose();
+
+        let airspeed = world_to_local * (object.velocity - wind);
+        let rate     = world_to_local * object.angular_velocity;
+
+        let mut force  = Vector::null();
+        let mut torque = Vector::null();
+
+        for n in 0 .. 3 {
+            force[n]  = -self.linear[n] * airspeed[n] - self.quadratic[n] * airspeed[n] * airspeed[n].abs();
+            torque[n] = -self.angular[n] * rate[n];
+        }
+
+        (local_to_world * force, local_to_world * torque)
+    }
+
+    //applies the drag and returns the force and torque
+    pub fn apply (&self, object_manager: &mut ObjectManager, tag: &ObjectTag, wind: Vector) -> (Vector, Vector) {
+        let (force, torque) = self.force_torque(object_manager.get_object(tag), wind);
+
+        object_manager.apply_force(force, tag);
+        object_manager.apply_torque(torque, tag);
+
+        (force, torque)
+    }
+}
+
+impl Default for Drag {
+    fn default () -> Drag {
+        Drag::new()
+    }
+}
+
+//discrete 1 - cosine gust, rises from zero to velocity and back within duration
+#[derive(Clone, Copy)]
+pub struct Gust {
+    pub start:    f32,
+    pub duration: f32,
+    pub velocity: Vector, //peak, world frame
+}
+
+#[allow(dead_code)]
+impl Gust {
+    pub fn velocity_at (&self, t: f32) -> Vector {
+        if t < self.start || t > self.start + self.duration {
+            return Vector::null();
+        }
+
+        let phase = (t - self.start) / self.duration;
+
+        self.velocity * (0.5f32 * (1f32 - (2f32 * ::std::f32::consts::PI * phase).cos()))
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TurbulenceModel {
+    Off,
+    Dryden,    //rational spectra, MIL-F-8785C
+    VonKarman, //closer to measured spectra, rational approximation of MIL-HDBK-1797
+}
+
+#[allow(dead_code)]
+impl TurbulenceModel {
+    pub fn all () -> [TurbulenceModel; 3] {
+        [TurbulenceModel::Off, TurbulenceModel::Dryden, TurbulenceModel::VonKarman]
+    }
+
+    pub fn name (&self) -> &'static str {
+        match *self {
+            TurbulenceModel::Off       => "off",
+            TurbulenceModel::Dryden    => "dryden",
+            TurbulenceModel::VonKarman => "von_karman",
+        }
+    }
+
+    pub fn from_name (name: &str) -> Option<TurbulenceModel> {
+        TurbulenceModel::all().iter().find(|model| model.name() == name).cloned()
+    }
+}
+
+//all vectors are longitudinal (along the mean wind), lateral and vertical
+#[derive(Clone, Copy)]
+pub struct Turbulence {
+    pub model:        TurbulenceModel,
+    pub intensity:    Vector, //standard deviation of the gust velocity, m/s
+    pub length_scale: Vector, //m, larger -> slower variations
+    pub airspeed:     Option<f32>, //how fast the turbulence is carried past the drone, None -> speed of the mean wind
+}
+
+#[allow(dead_code)]
+impl Turbulence {
+    pub fn new () -> Turbulence {
+        Turbulence {
+            model:        TurbulenceModel::Off,
+            intensity:    Vector::new(1f32, 1f32, 0.5f32, 0f32),
+            length_scale: Vector::new(50f32, 50f32, 10f32, 0f32),
+            airspeed:     None,
+        }
+    }
+}
+
+impl Default for Turbulence {
+    fn default () -> Turbulence {
+        Turbulence::new()
+    }
+}
+
+//continuous transfer function numerator(s) / denominator(s) driven by white noise, coefficients from s^0 up
+#[derive(Clone)]
+struct ShapingFilter {
+    numerator:   Vec<f32>,
+    denominator: Vec<f32>, //normalized to a leading 1
+    state:       Vec<f32>, //controllable canonical form
+    time_scale:  f32,
+}
+
+impl ShapingFilter {
+    fn new (numerator: Vec<f32>, denominator: Vec<f32>, time_scale: f32) -> ShapingFilter {
+        assert!(numerator.len() < denominator.len(), "shaping filter has to be strictly proper");
+
+        let leading = *denominator.last().unwrap();
+        let order = denominator.len() - 1;
+
+        ShapingFilter {
+            numerator:   numerator.iter().map(|b| b / leading).collect(),
+            denominator: denominator.iter().map(|a| a / leading).collect(),
+            state:       vec![0f32; order],
+            time_scale,
+        }
+    }
+
+    //advances by dt with the input held, returns the output
+    fn step (&mut self, input: f32, dt: f32) -> f32 {
+        let order = self.state.len();
+        let substeps = (dt / (FILTER_SUBSTEP * self.time_scale)).ceil().max(1f32) as usize;
+        let h = dt / substeps as f32;
+
+        for _ in 0 .. substeps {
+            let mut highest = input;
+            for n in 0 .. order {
+                highest -= self.denominator[n] * self.state[n];
+            }
+
+            for n in 0 .. order - 1 {
+                self.state[n] += h * self.state[n + 1];
+            }
+            self.state[order - 1] += h * highest;
+        }
+
+        self.numerator.iter().zip(self.state.iter()).map(|(b, x)| b * x).sum()
+    }
+}
+
+//spectrum of one turbulence component, longitudinal or not (lateral and vertical share theirs)
+fn shaping_filter (model: TurbulenceModel, longitudinal: bool, sigma: f32, length: f32, airspeed: f32) -> ShapingFilter {
+    let pi = ::std::f32::consts::PI;
+    let tau = length / airspeed;
+
+    match (model, longitudinal) {
+        (TurbulenceModel::Dryden, true) => ShapingFilter::new(
+            vec![sigma * (2f32 * tau / pi).sqrt()],
+            vec![1f32, tau],
+            tau,
+        ),
+        (TurbulenceModel::Dryden, false) => {
+            let gain = sigma * (tau / pi).sqrt();
+            ShapingFilter::new(
+                vec![gain, gain * 3f32.sqrt() * tau],
+                vec![1f32, 2f32 * tau, tau * tau],
+                tau,
+            )
+        },
+        (TurbulenceModel::VonKarman, true) => {
+            let gain = sigma * (2f32 * tau / pi).sqrt();
+            ShapingFilter::new(
+                vec![gain, gain * 0.25f32 * tau],
+                vec![1f32, 1.357f32 * tau, 0.1987f32 * tau * tau],
+                tau,
+            )
+        },
+        (TurbulenceModel::VonKarman, false) => {
+            let gain = sigma * (tau / pi).sqrt();
+            ShapingFilter::new(
+                vec![gain, gain * 2.7478f32 * tau, gain * 0.3398f32 * tau * tau],
+                vec![1f32, 2.9958f32 * tau, 1.9754f32 * tau * tau, 0.1539f32 * tau * tau * tau],
+                tau,
+            )
+        },
+        (TurbulenceModel::Off, _) => panic!("no shaping filter without turbulence"),
+    }
+}
+
+//the air around the objects, uniform in space: mean wind + gusts + turbulence
+pub struct Wind {
+    pub mean:       Vector, //world frame
+    pub gusts:      Vec<Gust>,
+    pub turbulence: Turbulence,
+    axes:           [Vector; 3], //world directions of the longitudinal, lateral and vertical turbulence
+    filters:        Vec<ShapingFilter>, //one per axis, empty without turbulence
+    fluctuation:    Vector, //current turbulence velocity, world frame
+    rng:            Random, //own stream, turning on turbulence doesn't change the disturbances of a seed
+}
+
+#[allow(dead_code)]
+impl Wind {
+    pub fn new (mean: Vector, gusts: Vec<Gust>, turbulence: Turbulence, rng: Random) -> Wind {
+        //longitudinal along the horizontal mean wind, world x in calm air, vertical is world y
+        let horizontal = Vector::new(mean.x, 0f32, mean.z, 0f32);
+        let longitudinal = Vector::normalize(horizontal).unwrap_or(Vector::ex());
+        let vertical = Vector::ey();
+        let lateral = Vector::cross(vertical, longitudinal);
+
+        let filters = if turbulence.model == TurbulenceModel::Off {
+            Vec::<ShapingFilter>::new()
+        } else {
+            let airspeed = turbulence.airspeed.unwrap_or(Vector::magnitude(mean)).max(MIN_AIRSPEED);
+
+            (0 .. 3).map(|n| {
+                assert!(turbulence.length_scale[n] > 0f32, "turbulence length scales must be positive");
+                shaping_filter(turbulence.model, n == 0, turbulence.intensity[n], turbulence.length_scale[n], airspeed)
+            }).collect()
+        };
+
+        Wind {
+            mean,
+            gusts,
+            turbulence,
+            axes:        [longitudinal, lateral, vertical],
+            filters,
+            fluctuation: Vector::null(),
+            rng,
+        }
+    }
+
+    pub fn calm () -> Wind {
+        Wind::new(Vector::null(), Vec::<Gust>::new(), Turbulence::new(), Random::new(0))
+    }
+
+    //advances the turbulence by dt
+    pub fn update (&mut self, dt: f32) {
+        if self.filters.is_empty() {
+            return;
+        }
+
+        //white noise with unit spectral density, held over the step
+        let scale = (::std::f32::consts::PI / dt).sqrt();
+
+        let mut fluctuation = Vector::null();
+        for (filter, axis) in self.filters.iter_mut().zip(self.axes.iter()) {
+            fluctuation += *axis * filter.step(scale * self.rng.gaussian(), dt);
+        }
+
+        self.fluctuation = fluctuation;
+    }
+
+    //world frame wind velocity at time t, the turbulence part is the one of the last update
+    pub fn velocity (&self, t: f32) -> Vector {
+        let mut velocity = self.mean + self.fluctuation;
+
+        for gust in &self.gusts {
+            velocity += gust.velocity_at(t);
+        }
+
+        velocity
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quaternion::Quaternion;
+
+    const DT :f32 = 0.01f32;
+
+    fn moving (velocity: Vector) -> Object {
+        let mut object = Object::new();
+        object.velocity = velocity;
+        object
+    }
+
+    //variance of every turbulence component over duration seconds in a 10 m/s wind along x
+    fn turbulence_variance (model: TurbulenceModel, duration: f32) -> Vector {
+        let mut turbulence = Turbulence::new();
+        turbulence.model = model;
+
+        let mean = Vector::ex() * 10f32;
+        let mut wind = Wind::new(mean, Vec::<Gust>::new(), turbulence, Random::new(7));
+
+        let steps = (duration / DT) as usize;
+        let mut sum = Vector::null();
+
+        for _ in 0 .. steps {
+            wind.update(DT);
+
+            let fluctuation = wind.velocity(0f32) - mean;
+            for n in 0 .. 3 {
+                sum[n] += fluctuation[n] * fluctuation[n];
+            }
+        }
+
+        sum / steps as f32
+    }
+
+    #[test]
+    fn drag_opposes_the_airspeed () {
+        let mut drag = Drag::new();
+        drag.linear    = Vector::new(1f32, 2f32, 3f32, 0f32);
+        drag.quadratic = Vector::new(0.5f32, 0.5f32, 0.5f32, 0f32);
+
+        let (forward, _)  = drag.force_torque(&moving(Vector::ex() * 2f32), Vector::null());
+        let (backward, _) = drag.force_torque(&moving(Vector::ex() * -2f32), Vector::null());
+
+        assert!((forward.x + 4f32).abs() < 1e-6f32, "drag {}", forward.x);
+        assert!((backward.x - 4f32).abs() < 1e-6f32, "drag {}", backward.x);
+        assert!(forward.y == 0f32 && forward.z == 0f32);
+
+        //only the air moving relative to the object counts
+        let (along, _) = drag.force_torque(&moving(Vector::ex() * 2f32), Vector::ex() * 2f32);
+        assert!(Vector::magnitude(along) == 0f32);
+
+        let (headwind, _) = drag.force_torque(&moving(Vector::null()), Vector::ey() * -1f32);
+        assert!((headwind.y + 2.5f32).abs() < 1e-6f32, "drag {}", headwind.y);
+    }
+
+    #[test]
+    fn quadratic_drag_goes_with_the_square () {
+        let mut drag = Drag::new();
+        drag.quadratic = Vector::new(0.3f32, 0.3f32, 0.3f32, 0f32);
+
+        let (slow, _) = drag.force_torque(&moving(Vector::ez() * 1.5f32), Vector::null());
+        let (fast, _) = drag.force_torque(&moving(Vector::ez() * 3f32), Vector::null());
+
+        assert!(slow.z < 0f32);
+        assert!((fast.z / slow.z - 4f32).abs() < 1e-5f32, "ratio {}", fast.z / slow.z);
+    }
+
+    #[test]
+    fn drag_coefficients_are_per_body_axis () {
+        let mut drag = Drag::new();
+        drag.linear  = Vector::new(1f32, 0f32, 4f32, 0f32);
+        drag.angular = Vector::new(0f32, 2f32, 0f32, 0f32);
+
+        //body z points along world x after a quarter turn around y
+        let mut object = moving(Vector::ex());
+        object.rotation = Quaternion::from_vector(Vector::ey() * (::std::f32::consts::PI * 0.5f32));
+        object.angular_velocity = Vector::ey() * 0.5f32;
+
+        let (force, torque) = drag.force_torque(&object, Vector::null());
+
+        assert!((force.x + 4f32).abs() < 1e-5f32, "drag {}", force.x);
+        assert!((torque.y + 1f32).abs() < 1e-5f32, "torque {}", torque.y);
+    }
+
+    #[test]
+    fn turbulence_has_the_requested_intensity () {
+        let intensity = Turbulence::new().intensity;
+
+        for &model in [TurbulenceModel::Dryden, TurbulenceModel::VonKarman].iter() {
+            let variance = turbulence_variance(model, 2000f32);
+
+            //longitudinal, lateral (-z) and vertical (y) in world axes
+            let expected = [intensity.x, intensity.y, intensity.z];
+            let measured = [variance.x, variance.z, variance.y];
+
+            for n in 0 .. 3 {
+                let ratio = measured[n] / (expected[n] * expected[n]);
+                assert!((0.5f32 .. 2f32).contains(&ratio), "{} component {} has {} of the variance", model.name(), n, ratio);
+            }
+        }
+    }
+
+    #[test]
+    fn calm_air_has_no_turbulence () {
+        let variance = turbulence_variance(TurbulenceModel::Off, 10f32);
+
+        assert!(Vector::magnitude(variance) == 0f32);
+    }
+}
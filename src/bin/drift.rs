@@ -0,0 +1,167 @@
+extern crate dronesim;
+
+use dronesim::object::Object;
+use dronesim::integrator::Integrator;
+use dronesim::matrix::Matrix;
+use dronesim::vector::Vector;
+
+use std::env;
+use std::time::Instant;
+
+/* measures how far each integrator drifts from quantities the exact solution conserves;
+
+tumbling  torque free asymmetric body spinning close to its unstable middle axis,
+          rotational energy and world space angular momentum stay constant
+spring    unit mass on a unit spring, the force is evaluated per stage (Integrator::step_in_field),
+          kinetic plus potential energy stays constant. with the force evaluated once per step, like the
+          simulation does, rk4 and lie_group move exactly for a constant force and gain energy on the spring
+
+usage: drift [--duration SECONDS] [--dt SECONDS]...
+*/
+
+const DEFAULT_DURATION :f32 = 60f32;
+const DEFAULT_DTS      :[f32; 3] = [0.02f32, 0.005f32, 0.001f32];
+
+fn rotational_energy (object: &Object) -> f32 {
+    let local_to_world = object.rotation.to_matrix();
+    let angular_velocity_local = local_to_world.transpose() * object.angular_velocity;
+
+    0.5f32 * Vector::dot(angular_velocity_local, object.inertia * angular_velocity_local)
+}
+
+fn angular_momentum (object: &Object) -> Vector {
+    let local_to_world = object.rotation.to_matrix();
+    let angular_velocity_local = local_to_world.transpose() * object.angular_velocity;
+
+    local_to_world * (object.inertia * angular_velocity_local)
+}
+
+fn spring_energy (object: &Object) -> f32 {
+    let x = object.position.to_translation();
+
+    0.5f32 * object.mass * Vector::dot(object.velocity, object.velocity) + 0.5f32 * Vector::dot(x, x)
+}
+
+//largest relative deviations of energy and momentum during the run, and microseconds per step
+fn tumbling (integrator: Integrator, dt: f32, duration: f32) -> (f32, f32, f32) {
+    let mut object = Object::new();
+    object.inertia = Matrix::symmetric(1f32, 2f32, 3f32, 0f32, 0f32, 0f32);
+    object.angular_velocity = Vector::new(0.05f32, 3f32, 0.05f32, 0f32);
+
+    let energy_start = rotational_energy(&object);
+    let momentum_start = angular_momentum(&object);
+
+    let mut energy_drift = 0f32;
+    let mut momentum_drift = 0f32;
+
+    let steps = (duration / dt).ceil() as u64;
+    let start = Instant::now();
+
+    for _ in 0 .. steps {
+        object.update(Vector::null(), Vector::null(), dt, integrator);
+
+        energy_drift = energy_drift.max((rotational_energy(&object) - energy_start).abs() / energy_start);
+        momentum_drift = momentum_drift.max(Vector::magnitude(angular_momentum(&object) - momentum_start) / Vector::magnitude(momentum_start));
+    }
+
+    (energy_drift, momentum_drift, microseconds(start) / steps as f32)
+}
+
+//largest relative deviation of the spring energy
+fn spring (integrator: Integrator, dt: f32, duration: f32) -> f32 {
+    let mut object = Object::new();
+    object.position = Vector::new(1f32, 0f32, 0f32, 1f32);
+
+    let energy_start = spring_energy(&object);
+    let mut energy_drift = 0f32;
+
+    for _ in 0 .. (duration / dt).ceil() as u64 {
+        integrator.step_in_field(&mut object, |position, _| -position.to_translation(), Vector::null(), dt);
+
+        energy_drift = energy_drift.max((spring_energy(&object) - energy_start).abs() / energy_start);
+    }
+
+    energy_drift
+}
+
+fn microseconds (start: Instant) -> f32 {
+    let elapsed = start.elapsed();
+    elapsed.as_secs() as f32 * 1e6f32 + elapsed.subsec_nanos() as f32 * 1e-3f32
+}
+
+fn main () {
+    let mut duration = DEFAULT_DURATION;
+    let mut dts = Vec::<f32>::new();
+
+    let mut args = env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--duration" => {
+                duration = args.next().expect("--duration requires a value").parse().expect("--duration requires a number");
+            },
+            "--dt" => {
+                dts.push(args.next().expect("--dt requires a value").parse().expect("--dt requires a number"));
+            },
+            _ => {
+                println!("unknown argument: {}", arg);
+                println!("usage: drift [--duration SECONDS] [--dt SECONDS]...");
+                panic!();
+            },
+        }
+    }
+
+    if dts.is_empty() {
+        dts.extend_from_slice(&DEFAULT_DTS);
+    }
+
+    println!("drift over {} seconds, relative to the initial value", duration);
+    println!("{:<20} {:>8} {:>16} {:>16} {:>16} {:>10}", "integrator", "dt", "tumbling energy", "tumbling moment.", "spring energy", "us/step");
+
+    for &dt in &dts {
+        for integrator in Integrator::all().iter() {
+            let (energy_drift, momentum_drift, step_time) = tumbling(*integrator, dt, duration);
+            let spring_drift = spring(*integrator, dt, duration);
+
+            println!("{:<20} {:>8} {:>16.3e} {:>16.3e} {:>16.3e} {:>10.3}", integrator.name(), dt, energy_drift, momentum_drift, spring_drift, step_time);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DURATION :f32 = 60f32;
+
+    #[test]
+    fn higher_order_integrators_keep_the_tumbling_energy () {
+        for &integrator in [Integrator::Rk4, Integrator::LieGroup].iter() {
+            let (energy_drift, momentum_drift, _) = tumbling(integrator, 0.005f32, DURATION);
+
+            assert!(energy_drift < 1e-4f32, "{} energy drift {}", integrator.name(), energy_drift);
+            assert!(momentum_drift < 1e-4f32, "{} momentum drift {}", integrator.name(), momentum_drift);
+        }
+    }
+
+    #[test]
+    fn verlet_drifts_less_than_euler () {
+        let (euler, _, _) = tumbling(Integrator::SemiImplicitEuler, 0.005f32, DURATION);
+        let (verlet, _, _) = tumbling(Integrator::Verlet, 0.005f32, DURATION);
+
+        assert!(euler < 0.5f32, "semi_implicit_euler energy drift {}", euler);
+        assert!(verlet < euler * 0.6f32, "verlet energy drift {} against {}", verlet, euler);
+    }
+
+    #[test]
+    fn spring_energy_stays_bounded () {
+        let euler = spring(Integrator::SemiImplicitEuler, 0.005f32, DURATION);
+        assert!(euler < 5e-3f32, "semi_implicit_euler spring energy drift {}", euler);
+
+        for &integrator in [Integrator::Verlet, Integrator::Rk4, Integrator::LieGroup].iter() {
+            let drift = spring(integrator, 0.005f32, DURATION);
+
+            assert!(drift < euler * 0.1f32, "{} spring energy drift {} against {}", integrator.name(), drift, euler);
+        }
+    }
+}
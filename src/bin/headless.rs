@@ -0,0 +1,148 @@
+extern crate dronesim;
+
+use dronesim::simulation::Simulation;
+use dronesim::scenario::Scenario;
+use dronesim::vector::Vector;
+use dronesim::recorder::Recorder;
+use dronesim::evaluation::Evaluator;
+use dronesim::integrator::Integrator;
+use dronesim::scheduler::Task;
+
+use std::env;
+use std::process;
+
+//how long a headless run lasts, either in steps or in simulated seconds
+enum RunLength {
+    Steps(u64),
+    Duration(f32),
+}
+
+struct Args {
+    scenario_file: String,
+    run_length:    Option<RunLength>, //None -> duration from the scenario
+    seed:          Option<u64>,       //overrides the seed from the scenario
+    integrator:    Option<Integrator>, //overrides the integrator from the scenario
+    csv_file:      Option<String>,
+    record_file:   Option<String>,    //binary flight log
+}
+
+fn parse_args () -> Args {
+    let mut result = Args {
+        scenario_file: "scenarios/test_flight.txt".to_string(),
+        run_length:    None,
+        seed:          None,
+        integrator:    None,
+        csv_file:      None,
+        record_file:   None,
+    };
+
+    let mut args = env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--steps" => {
+                let value = args.next().expect("--steps requires a value");
+                result.run_length = Some(RunLength::Steps(value.parse().expect("--steps requires an integer")));
+            },
+            "--duration" => {
+                let value = args.next().expect("--duration requires a value");
+                result.run_length = Some(RunLength::Duration(value.parse().expect("--duration requires a number")));
+            },
+            "--seed" => {
+                let value = args.next().expect("--seed requires a value");
+                result.seed = Some(value.parse().expect("--seed requires an unsigned integer"));
+            },
+            "--integrator" => {
+                let value = args.next().expect("--integrator requires a value");
+                result.integrator = Some(Integrator::from_name(value.as_str()).expect("--integrator expects semi_implicit_euler, rk4, verlet or lie_group"));
+            },
+            "--csv" => {
+                result.csv_file = Some(args.next().expect("--csv requires a filename"));
+            },
+            "--record" => {
+                result.record_file = Some(args.next().expect("--record requires a filename"));
+            },
+            _ if !arg.starts_with("--") => {
+                result.scenario_file = arg;
+            },
+            _ => {
+                println!("unknown argument: {}", arg);
+                println!("usage: headless [SCENARIO] [--steps N | --duration SECONDS] [--seed SEED] [--integrator NAME] [--csv FILE] [--record FILE]");
+                panic!();
+            },
+        }
+    }
+
+    result
+}
+
+fn main () {
+    let args = parse_args();
+
+    let mut scenario = Scenario::load(args.scenario_file.as_str());
+    if args.seed.is_some() {
+        scenario.seed = args.seed;
+    }
+    if let Some(integrator) = args.integrator {
+        scenario.integrator = integrator;
+    }
+
+    let mut sim = Simulation::new(&scenario);
+
+    let steps = match args.run_length.unwrap_or(RunLength::Duration(scenario.duration)) {
+        RunLength::Steps(n) => n,
+        RunLength::Duration(duration) => (duration / scenario.dt).ceil() as u64,
+    };
+
+    println!("seed: {}", sim.rng.seed());
+    for task in Task::all().iter() {
+        let timing = sim.scheduler.timing(*task);
+        println!("{}: every {} s, phase {} s, latency {} s", task.name(), timing.period, timing.phase, timing.latency);
+    }
+    println!("running headless for {} steps!", steps);
+
+    let mut recorder = Recorder::new(
+        sim.motors.len(),
+        args.csv_file.as_deref(),
+        args.record_file.as_deref(),
+    );
+
+    let mut evaluator = Evaluator::new(&scenario.criteria);
+
+    for _ in 0 .. steps {
+        let record = sim.step();
+        recorder.record(&record);
+        evaluator.add(&record);
+
+        for collision in &sim.collisions {
+            let other = match collision.b {
+                Some(ref tag) => sim.name(tag),
+                None => "the ground",
+            };
+            println!("t {}: {} hit {}", sim.t, sim.name(&collision.a), other);
+        }
+    }
+
+    recorder.flush();
+
+    let obj = sim.object_manager.get_object(&sim.drone);
+    let p_target = sim.spline.sample(sim.t % sim.spline.duration());
+
+    println!("t: {}", sim.t);
+    print!("drone position: "); obj.position.print();
+    println!("tracking error: {}", Vector::magnitude(obj.position - p_target));
+
+    let metrics = evaluator.finish();
+    metrics.print();
+
+    let failures = metrics.check(&scenario.criteria);
+
+    if failures.is_empty() {
+        println!("PASS");
+    } else {
+        for failure in &failures {
+            println!("FAIL: {}", failure);
+        }
+        process::exit(1);
+    }
+}
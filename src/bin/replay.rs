@@ -0,0 +1,165 @@
+extern crate dronesim;
+
+use dronesim::graphicsmanager::GraphicsManager;
+use dronesim::graphicsmanager::Key;
+use dronesim::object::Object;
+use dronesim::objectmanager::ObjectManager;
+use dronesim::objectmanager::ObjectTag;
+use dronesim::quaternion::Quaternion;
+use dronesim::recorder;
+use dronesim::recorder::Record;
+use dronesim::scenario::Scenario;
+use dronesim::scenario::Role;
+
+use std::env;
+use std::time::Instant;
+
+const SEEK_STEP :f32 = 5f32; //seconds
+
+/* controls;
+space         play / pause
+left / right  seek 5 seconds back / forward
+up / down     double / halve playback speed
+, / .         step one frame back / forward (pauses)
+home          back to the start
+*/
+
+//index of the last record with record.t <= t
+fn find_index (records: &[Record], t: f32) -> usize {
+    match records.binary_search_by(|record| record.t.total_cmp(&t)) {
+        Ok(n) => n,
+        Err(0) => 0,
+        Err(n) => n - 1,
+    }
+}
+
+fn main () {
+    let mut args = env::args().skip(1);
+
+    let record_file = match args.next() {
+        Some(filename) => filename,
+        None => {
+            println!("usage: replay RECORDING [SCENARIO]");
+            panic!();
+        },
+    };
+
+    let records = recorder::read_binary(record_file.as_str());
+    assert!(!records.is_empty(), "{} contains no records", record_file);
+
+    let mut drone = Object::with_model("drone");
+    drone.scale = 0.6f32;
+
+    let mut scene   = ObjectManager::new(); //everything but the drone
+    let mut targets = Vec::<(ObjectTag, f32)>::new(); //with their lead time
+    let scenario    = args.next().map(|scenario_file| Scenario::load(scenario_file.as_str()));
+
+    match scenario {
+        Some(ref scenario) => { //the drone looks like in its scenario, the other objects start where it puts them
+            for description in &scenario.objects {
+                if let Role::Drone = description.role {
+                    drone.model = description.model.clone();
+                    drone.scale = description.scale;
+                    continue;
+                }
+
+                let mut object = Object::new();
+                object.model    = description.model.clone();
+                object.scale    = description.scale;
+                object.position = description.position;
+                object.rotation = Quaternion::from_vector(description.rotation);
+
+                let tag = scene.push_object_tagged(object);
+
+                if let Role::Target(lead) = description.role {
+                    targets.push((tag, lead));
+                }
+            }
+        },
+        None => { //a cube on the reference
+            let mut reference = Object::with_model("cube");
+            reference.scale = 0.25f32;
+
+            targets.push((scene.push_object_tagged(reference), 0f32));
+        },
+    }
+
+    let t_start = records[0].t;
+    let t_end   = records[records.len() - 1].t;
+
+    let mut gm = GraphicsManager::new();
+
+    let mut playing = true;
+    let mut speed   = 1f32;
+    let mut index   = 0;
+    let mut t       = t_start; //playback time
+
+    let mut last_frame = Instant::now();
+
+    println!("replaying {} records, t = {} .. {}", records.len(), t_start, t_end);
+
+    loop {
+        let input = gm.poll_input();
+
+        if input.closed {
+            break;
+        }
+
+        let now = Instant::now();
+        let elapsed = now.duration_since(last_frame);
+        let frame_dt = elapsed.as_secs() as f32 + elapsed.subsec_nanos() as f32 * 1e-9f32;
+        last_frame = now;
+
+        if playing {
+            t += frame_dt * speed;
+        }
+
+        for key in input.keys {
+            match key {
+                Key::Space  => playing = !playing,
+                Key::Left   => t -= SEEK_STEP,
+                Key::Right  => t += SEEK_STEP,
+                Key::Up     => speed *= 2f32,
+                Key::Down   => speed *= 0.5f32,
+                Key::Home   => t = t_start,
+                Key::Comma  => {
+                    playing = false;
+                    t = records[index.max(1) - 1].t;
+                },
+                Key::Period => {
+                    playing = false;
+                    t = records[(index + 1).min(records.len() - 1)].t;
+                },
+            }
+
+            println!("t: {} speed: {}x {}", t, speed, if playing { "playing" } else { "paused" });
+        }
+
+        t = t.max(t_start).min(t_end);
+        index = find_index(&records, t);
+
+        if t >= t_end {
+            playing = false;
+        }
+
+        {
+            let record = &records[index];
+
+            drone.position = record.position;
+            drone.rotation = record.rotation;
+
+            for &(ref tag, lead) in &targets {
+                scene.get_mut_object(tag).position = match scenario {
+                    Some(ref scenario) => scenario.spline.sample((record.t + lead) % scenario.spline.duration()),
+                    None => record.reference_position,
+                };
+            }
+        }
+
+        //rendering
+        gm.setup();
+        scene.draw(&mut gm);
+        gm.draw_object(&drone);
+        gm.finish_frame();
+    }
+}
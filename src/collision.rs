@@ -0,0 +1,723 @@
+use vector::Vector;
+use matrix::Matrix;
+use object::Object;
+use objectmanager::ObjectTag;
+use utils;
+
+const GJK_ITERATIONS :usize = 64;
+const EPA_ITERATIONS :usize = 64;
+const TOLERANCE      :f32 = 1e-5f32; //m, distances below count as touching cores
+const EPA_TOLERANCE  :f32 = 1e-4f32; //m, accuracy of the penetration depth
+
+//convex shape in the body frame of its object, not scaled with Object::scale
+#[derive(Clone)]
+pub enum Shape {
+    Sphere  { radius: f32 },
+    Box     { half_extents: Vector },
+    Capsule { radius: f32, half_length: f32 }, //along the body y axis, half_length without the caps
+    Hull    { points: Vec<Vector> },           //convex hull of the points
+}
+
+#[allow(dead_code)]
+impl Shape {
+    //hull of the vertices of a graphics model, scaled like the drawn object
+    pub fn hull_from_model (model: &str, scale: f32) -> Shape {
+        let vertex_file = utils::read_file((model.to_string() + "_vertices.txt").as_str());
+
+        let numbers :Vec<f32> = vertex_file.split_whitespace().filter_map(|number| number.parse().ok()).collect();
+        assert!(numbers.len() % 6 == 0 && !numbers.is_empty(), "{}_vertices.txt has no vertices", model);
+
+        //position and color of every vertex
+        let points = numbers.chunks(6).map(|vertex| Vector::new(vertex[0], vertex[1], vertex[2], 0f32) * scale).collect();
+
+        Shape::Hull { points }
+    }
+
+    //the shape is its core grown by this radius in every direction
+    fn margin (&self) -> f32 {
+        match *self {
+            Shape::Sphere { radius }      => radius,
+            Shape::Capsule { radius, .. } => radius,
+            Shape::Box { .. }             => 0f32,
+            Shape::Hull { .. }            => 0f32,
+        }
+    }
+
+    //corners of the core, body frame
+    fn core_vertices (&self) -> Vec<Vector> {
+        match *self {
+            Shape::Sphere { .. } => vec![Vector::null()],
+            Shape::Capsule { half_length, .. } => vec![Vector::ey() * half_length, Vector::ey() * -half_length],
+            Shape::Box { half_extents } => (0 .. 8).map(|n| Vector::new(
+                if n & 1 == 0 { -half_extents.x } else { half_extents.x },
+                if n & 2 == 0 { -half_extents.y } else { half_extents.y },
+                if n & 4 == 0 { -half_extents.z } else { half_extents.z },
+                0f32,
+            )).collect(),
+            Shape::Hull { ref points } => points.clone(),
+        }
+    }
+
+    //point of the core farthest along direction, body frame
+    fn core_support (&self, direction: Vector) -> Vector {
+        let sign = |value: f32| if value < 0f32 { -1f32 } else { 1f32 };
+
+        match *self {
+            Shape::Sphere { .. } => Vector::null(),
+            Shape::Capsule { half_length, .. } => Vector::ey() * (sign(direction.y) * half_length),
+            Shape::Box { half_extents } => Vector::new(
+                sign(direction.x) * half_extents.x,
+                sign(direction.y) * half_extents.y,
+                sign(direction.z) * half_extents.z,
+                0f32,
+            ),
+            Shape::Hull { ref points } => {
+                let mut best = points[0];
+                for point in points.iter() {
+                    if Vector::dot(*point, direction) > Vector::dot(best, direction) {
+                        best = *point;
+                    }
+                }
+                best
+            },
+        }
+    }
+}
+
+//a shape at the pose of its object
+struct Placed<'a> {
+    shape:          &'a Shape,
+    position:       Vector,
+    local_to_world: Matrix,
+    world_to_local: Matrix,
+}
+
+impl<'a> Placed<'a> {
+    fn new (shape: &'a Shape, object: &Object) -> Placed<'a> {
+        let local_to_world = object.rotation.to_matrix();
+
+        Placed {
+            shape,
+            position:       object.position,
+            local_to_world,
+            world_to_local: local_to_world.transpose(),
+        }
+    }
+
+    //a shape that doesn't turn, e.g. a sphere around a corner of another shape
+    fn at (shape: &'a Shape, position: Vector) -> Placed<'a> {
+        Placed {
+            shape,
+            position,
+            local_to_world: Matrix::identity(),
+            world_to_local: Matrix::identity(),
+        }
+    }
+
+    //world positions of the corners of the core
+    fn vertices (&self) -> Vec<Vector> {
+        self.shape.core_vertices().iter().map(|vertex| self.position + self.local_to_world * *vertex).collect()
+    }
+
+    //world position of the core point farthest along the world direction
+    fn support (&self, direction: Vector) -> Vector {
+        self.position + self.local_to_world * self.shape.core_support(self.world_to_local * direction)
+    }
+
+    fn margin (&self) -> f32 {
+        self.shape.margin()
+    }
+}
+
+//axis aligned bounding box, world frame
+#[derive(Clone, Copy)]
+pub struct Aabb {
+    pub min: Vector,
+    pub max: Vector,
+}
+
+#[allow(dead_code)]
+impl Aabb {
+    pub fn of (shape: &Shape, object: &Object) -> Aabb {
+        let placed = Placed::new(shape, object);
+        let r = placed.margin();
+
+        Aabb {
+            min: Vector::new(placed.support(-Vector::ex()).x - r, placed.support(-Vector::ey()).y - r, placed.support(-Vector::ez()).z - r, 1f32),
+            max: Vector::new(placed.support(Vector::ex()).x + r, placed.support(Vector::ey()).y + r, placed.support(Vector::ez()).z + r, 1f32),
+        }
+    }
+
+    //true if the boxes are less than margin apart on every axis
+    pub fn overlaps (&self, other: &Aabb, margin: f32) -> bool {
+        self.min.x <= other.max.x + margin && other.min.x <= self.max.x + margin &&
+        self.min.y <= other.max.y + margin && other.min.y <= self.max.y + margin &&
+        self.min.z <= other.max.z + margin && other.min.z <= self.max.z + margin
+    }
+}
+
+//surface properties for the contact response
+#[derive(Clone, Copy)]
+pub struct Material {
+    pub restitution: f32, //share of the approach speed that bounces back, 0 .. 1
+    pub friction:    f32, //coulomb coefficient, tangential impulse per normal impulse
+}
+
+#[allow(dead_code)]
+impl Material {
+    pub fn new () -> Material {
+        Material {
+            restitution: 0.2f32,
+            friction:    0.5f32,
+        }
+    }
+
+    //the bouncier surface bounces, friction is the geometric mean
+    pub fn combine (&self, other: &Material) -> Material {
+        Material {
+            restitution: self.restitution.max(other.restitution),
+            friction:    (self.friction * other.friction).sqrt(),
+        }
+    }
+}
+
+impl Default for Material {
+    fn default () -> Material {
+        Material::new()
+    }
+}
+
+//two shapes, or a shape and the ground, closer than the near miss distance
+#[derive(Clone, Copy)]
+pub struct Contact {
+    pub a:      ObjectTag,
+    pub b:      Option<ObjectTag>, //None -> the ground
+    pub normal: Vector, //world frame, points from b to a, moving a along it separates them
+    pub depth:  f32,    //how far the shapes overlap, negative -> gap between them
+    pub point:  Vector, //halfway between the closest (or deepest) points of the surfaces
+}
+
+#[allow(dead_code)]
+impl Contact {
+    pub fn is_touching (&self) -> bool {
+        self.depth >= 0f32
+    }
+
+    pub fn involves (&self, tag: &ObjectTag) -> bool {
+        self.a == *tag || self.b == Some(*tag)
+    }
+
+    pub fn same_pair (&self, other: &Contact) -> bool {
+        self.a == other.a && self.b == other.b
+    }
+}
+
+//normal (from b to a), depth and point of two shapes less than margin apart, None otherwise
+pub fn collide (shape_a: &Shape, object_a: &Object, shape_b: &Shape, object_b: &Object, margin: f32) -> Option<(Vector, f32, Vector)> {
+    collide_placed(&Placed::new(shape_a, object_a), &Placed::new(shape_b, object_b), margin)
+}
+
+fn collide_placed (a: &Placed, b: &Placed, margin: f32) -> Option<(Vector, f32, Vector)> {
+    let (normal, core_depth, point_a, point_b) = match gjk(a, b) {
+        Gjk::Separated { distance, point_a, point_b } => {
+            if distance - a.margin() - b.margin() > margin {
+                return None;
+            }
+
+            ((point_a - point_b) / distance, -distance, point_a, point_b)
+        },
+        Gjk::Overlapping(simplex) => epa(a, b, simplex),
+    };
+
+    let surface_a = point_a - normal * a.margin();
+    let surface_b = point_b + normal * b.margin();
+
+    Some((normal, core_depth + a.margin() + b.margin(), surface_a + (surface_b - surface_a) * 0.5f32))
+}
+
+//same against the ground plane y = height
+pub fn collide_ground (shape: &Shape, object: &Object, height: f32, margin: f32) -> Option<(Vector, f32, Vector)> {
+    let placed = Placed::new(shape, object);
+
+    let lowest = placed.support(-Vector::ey()) - Vector::ey() * placed.margin();
+    let depth = height - lowest.y;
+
+    if depth < -margin {
+        return None;
+    }
+
+    Some((Vector::ey(), depth, Vector::new(lowest.x, 0.5f32 * (lowest.y + height), lowest.z, 1f32)))
+}
+
+//depth and point of every core vertex of a and b that is within tolerance of the other shape,
+//so a face resting on a face is held at its corners, falls back to the single contact (round shapes, crossing edges)
+pub fn manifold (shape_a: &Shape, object_a: &Object, shape_b: &Shape, object_b: &Object, contact: &Contact, tolerance: f32) -> Vec<(f32, Vector)> {
+    let a = Placed::new(shape_a, object_a);
+    let b = Placed::new(shape_b, object_b);
+
+    let mut points = Vec::<(f32, Vector)>::new();
+
+    for vertex in a.vertices() {
+        let corner = Shape::Sphere { radius: a.margin() };
+        if let Some((_, depth, point)) = collide_placed(&Placed::at(&corner, vertex), &b, tolerance) {
+            points.push((depth, point));
+        }
+    }
+
+    for vertex in b.vertices() {
+        let corner = Shape::Sphere { radius: b.margin() };
+        if let Some((_, depth, point)) = collide_placed(&a, &Placed::at(&corner, vertex), tolerance) {
+            points.push((depth, point));
+        }
+    }
+
+    if points.is_empty() {
+        points.push((contact.depth, contact.point));
+    }
+
+    points
+}
+
+//same against the ground plane y = height, the normal is always world y
+pub fn ground_manifold (shape: &Shape, object: &Object, height: f32, tolerance: f32) -> Vec<(f32, Vector)> {
+    let placed = Placed::new(shape, object);
+    let r = placed.margin();
+
+    placed.vertices().iter().filter_map(|vertex| {
+        let depth = height - (vertex.y - r);
+
+        if depth < -tolerance {
+            None
+        } else {
+            Some((depth, Vector::new(vertex.x, 0.5f32 * (vertex.y - r + height), vertex.z, 1f32)))
+        }
+    }).collect()
+}
+
+//point of the minkowski difference a - b with the points of both shapes it came from
+#[derive(Clone, Copy)]
+struct Vertex {
+    w: Vector,
+    a: Vector,
+    b: Vector,
+}
+
+fn support (a: &Placed, b: &Placed, direction: Vector) -> Vertex {
+    let point_a = a.support(direction);
+    let point_b = b.support(-direction);
+
+    Vertex {
+        w: point_a - point_b,
+        a: point_a,
+        b: point_b,
+    }
+}
+
+//affine combination of positions, keeps w exact
+fn combine (points: &[Vector], weights: &[f32]) -> Vector {
+    let mut result = points[0];
+    for n in 1 .. points.len() {
+        result += (points[n] - points[0]) * weights[n];
+    }
+    result
+}
+
+//closest point to the origin on the convex hull of up to four vertices, brute force over every face, edge and vertex,
+//returns the vertices it lies between, their weights and the point
+fn closest_on_simplex (simplex: &[Vertex]) -> (Vec<Vertex>, Vec<f32>, Vector) {
+    let mut best :Option<(Vec<Vertex>, Vec<f32>, Vector)> = None;
+
+    for mask in 1 .. 1usize << simplex.len() {
+        let subset :Vec<Vertex> = (0 .. simplex.len()).filter(|n| mask & (1 << n) != 0).map(|n| simplex[n]).collect();
+        let points :Vec<Vector> = subset.iter().map(|vertex| vertex.w).collect();
+
+        let weights = match affine_projection(&points) {
+            Some(weights) => weights,
+            None => continue, //degenerate, covered by a smaller subset
+        };
+
+        if weights.iter().any(|&weight| weight < 0f32) {
+            continue; //the projection lies outside of this face
+        }
+
+        let point = combine(&points, &weights);
+
+        let better = match best {
+            Some((_, _, best_point)) => Vector::dot(point, point) < Vector::dot(best_point, best_point),
+            None => true,
+        };
+
+        if better {
+            best = Some((subset, weights, point));
+        }
+    }
+
+    best.expect("a single vertex is always a valid subset")
+}
+
+//weights of the projection of the origin onto the affine hull of the points, None if they are degenerate
+fn affine_projection (points: &[Vector]) -> Option<Vec<f32>> {
+    let k = points.len() - 1;
+
+    if k == 0 {
+        return Some(vec![1f32]);
+    }
+
+    //gram matrix of the edges from the first point, solved for the edge weights
+    let edges :Vec<Vector> = points[1 ..].iter().map(|&point| point - points[0]).collect();
+
+    let mut system = [[0f32; 4]; 3];
+    let mut scale = 0f32;
+
+    for i in 0 .. k {
+        for j in 0 .. k {
+            system[i][j] = Vector::dot(edges[i], edges[j]);
+        }
+        system[i][k] = -Vector::dot(edges[i], points[0]);
+        scale = scale.max(system[i][i]);
+    }
+
+    for column in 0 .. k {
+        let mut pivot = column;
+        for row in column + 1 .. k {
+            if system[row][column].abs() > system[pivot][column].abs() {
+                pivot = row;
+            }
+        }
+
+        if system[pivot][column].abs() <= 1e-6f32 * scale {
+            return None;
+        }
+
+        system.swap(column, pivot);
+        let pivot_row = system[column];
+
+        for (row, equation) in system.iter_mut().enumerate().take(k) {
+            if row != column {
+                let factor = equation[column] / pivot_row[column];
+                for (value, pivot) in equation[column .. k + 1].iter_mut().zip(&pivot_row[column .. k + 1]) {
+                    *value -= factor * pivot;
+                }
+            }
+        }
+    }
+
+    let mut weights = vec![1f32; k + 1];
+    for i in 0 .. k {
+        weights[i + 1] = system[i][k] / system[i][i];
+        weights[0] -= weights[i + 1];
+    }
+
+    Some(weights)
+}
+
+enum Gjk {
+    Separated { distance: f32, point_a: Vector, point_b: Vector }, //closest points of the cores
+    Overlapping(Vec<Vertex>),                                       //simplex containing the origin
+}
+
+//gilbert-johnson-keerthi distance between the cores
+fn gjk (a: &Placed, b: &Placed) -> Gjk {
+    let start = a.position - b.position;
+    let start = if Vector::dot(start, start) > 0f32 { start } else { Vector::ex() };
+
+    let mut simplex = vec![support(a, b, -start)];
+    let mut weights = vec![1f32];
+    let mut v = simplex[0].w;
+
+    for _ in 0 .. GJK_ITERATIONS {
+        let vv = Vector::dot(v, v);
+
+        if vv < TOLERANCE * TOLERANCE {
+            return Gjk::Overlapping(simplex);
+        }
+
+        let w = support(a, b, -v);
+
+        //no point of the difference is closer to the origin than v along v
+        if vv - Vector::dot(v, w.w) <= TOLERANCE * vv.sqrt() {
+            break;
+        }
+
+        let mut candidate = simplex.clone();
+        candidate.push(w);
+
+        let (next, next_weights, next_v) = closest_on_simplex(&candidate);
+
+        if next.len() == 4 {
+            return Gjk::Overlapping(next);
+        }
+
+        if Vector::dot(next_v, next_v) >= vv {
+            break; //no progress, rounding
+        }
+
+        simplex = next;
+        weights = next_weights;
+        v = next_v;
+    }
+
+    let points_a :Vec<Vector> = simplex.iter().map(|vertex| vertex.a).collect();
+    let points_b :Vec<Vector> = simplex.iter().map(|vertex| vertex.b).collect();
+
+    Gjk::Separated {
+        distance: Vector::magnitude(v),
+        point_a:  combine(&points_a, &weights),
+        point_b:  combine(&points_b, &weights),
+    }
+}
+
+struct Face {
+    vertices: [usize; 3], //counter clockwise seen from outside
+    normal:   Vector,     //unit, outwards
+    distance: f32,        //of the plane from the origin
+}
+
+fn make_face (polytope: &[Vertex], i: usize, j: usize, k: usize) -> Face {
+    let normal = Vector::cross(polytope[j].w - polytope[i].w, polytope[k].w - polytope[i].w);
+
+    match Vector::normalize(normal) {
+        Some(normal) => Face {
+            vertices: [i, j, k],
+            normal,
+            distance: Vector::dot(normal, polytope[i].w),
+        },
+        None => Face {
+            vertices: [i, j, k],
+            normal:   Vector::ex(),
+            distance: f32::INFINITY, //degenerate, never the closest
+        },
+    }
+}
+
+fn closest_face (faces: &[Face]) -> usize {
+    let mut closest = 0;
+    for n in 1 .. faces.len() {
+        if faces[n].distance < faces[closest].distance {
+            closest = n;
+        }
+    }
+    closest
+}
+
+//expanding polytope algorithm, penetration of overlapping cores,
+//returns the normal (from b to a), the depth and the deepest points of both cores
+fn epa (a: &Placed, b: &Placed, simplex: Vec<Vertex>) -> (Vector, f32, Vector, Vector) {
+    let mut polytope = simplex;
+
+    //grow the simplex from gjk into a tetrahedron, it still contains the origin
+    let axes = [Vector::ex(), Vector::ey(), Vector::ez(), -Vector::ex(), -Vector::ey(), -Vector::ez()];
+
+    if polytope.len() == 1 {
+        for axis in axes.iter() {
+            let w = support(a, b, *axis);
+            if Vector::magnitude(w.w - polytope[0].w) > TOLERANCE {
+                polytope.push(w);
+                break;
+            }
+        }
+    }
+
+    if polytope.len() == 2 {
+        let edge = polytope[1].w - polytope[0].w;
+
+        'search_line: for axis in axes[.. 3].iter() {
+            if let Some(normal) = Vector::normalize(Vector::cross(edge, *axis)) {
+                for &direction in [normal, -normal].iter() {
+                    let w = support(a, b, direction);
+                    if Vector::dot(w.w - polytope[0].w, direction) > TOLERANCE {
+                        polytope.push(w);
+                        break 'search_line;
+                    }
+                }
+            }
+        }
+    }
+
+    if polytope.len() == 3 {
+        if let Some(normal) = Vector::normalize(Vector::cross(polytope[1].w - polytope[0].w, polytope[2].w - polytope[0].w)) {
+            for &direction in [normal, -normal].iter() {
+                let w = support(a, b, direction);
+                if Vector::dot(w.w - polytope[0].w, direction) > TOLERANCE {
+                    polytope.push(w);
+                    break;
+                }
+            }
+        }
+    }
+
+    if polytope.len() < 4 {
+        //the difference is flat, the shapes just touch
+        let normal = Vector::normalize(a.position - b.position).unwrap_or(Vector::ey());
+        let point_a = a.support(-normal);
+        let point_b = b.support(normal);
+        return (normal, 0f32, point_a, point_b);
+    }
+
+    let mut faces = Vec::<Face>::new();
+    {
+        let centroid = (polytope[0].w + polytope[1].w + polytope[2].w + polytope[3].w) * 0.25f32;
+
+        for &(i, j, k) in [(0, 1, 2), (0, 3, 1), (0, 2, 3), (1, 3, 2)].iter() {
+            let candidate = make_face(&polytope, i, j, k);
+
+            if Vector::dot(candidate.normal, polytope[i].w - centroid) < 0f32 {
+                faces.push(make_face(&polytope, i, k, j));
+            } else {
+                faces.push(candidate);
+            }
+        }
+    }
+
+    for _ in 0 .. EPA_ITERATIONS {
+        let closest = closest_face(&faces);
+        let normal = faces[closest].normal;
+        let w = support(a, b, normal);
+
+        if Vector::dot(w.w, normal) - faces[closest].distance < EPA_TOLERANCE {
+            break;
+        }
+
+        polytope.push(w);
+        let new = polytope.len() - 1;
+
+        //remove the faces w sees, the edges only one of them had form the horizon
+        let mut horizon = Vec::<(usize, usize)>::new();
+        let mut kept = Vec::<Face>::new();
+
+        for face in faces.drain(..) {
+            if Vector::dot(face.normal, w.w - polytope[face.vertices[0]].w) > 0f32 {
+                for n in 0 .. 3 {
+                    let edge = (face.vertices[n], face.vertices[(n + 1) % 3]);
+
+                    match horizon.iter().position(|&other| other == (edge.1, edge.0)) {
+                        Some(shared) => { horizon.remove(shared); },
+                        None => horizon.push(edge),
+                    }
+                }
+            } else {
+                kept.push(face);
+            }
+        }
+
+        faces = kept;
+
+        for &(i, j) in &horizon {
+            faces.push(make_face(&polytope, i, j, new));
+        }
+    }
+
+    //the origin projected onto the closest face, in its barycentric coordinates
+    let face = &faces[closest_face(&faces)];
+    let vertices = [polytope[face.vertices[0]], polytope[face.vertices[1]], polytope[face.vertices[2]]];
+
+    let weights = affine_projection(&[vertices[0].w, vertices[1].w, vertices[2].w]).unwrap_or(vec![1f32, 0f32, 0f32]);
+
+    let point_a = combine(&[vertices[0].a, vertices[1].a, vertices[2].a], &weights);
+    let point_b = combine(&[vertices[0].b, vertices[1].b, vertices[2].b], &weights);
+
+    (-face.normal, face.distance, point_a, point_b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quaternion::Quaternion;
+    use objectmanager::ObjectManager;
+
+    fn object_at (x: f32, y: f32, z: f32) -> Object {
+        let mut object = Object::new();
+        object.position = Vector::new(x, y, z, 1f32);
+        object
+    }
+
+    fn assert_close (actual: Vector, expected: Vector, tolerance: f32) {
+        assert!(Vector::magnitude(actual - expected) < tolerance, "{:?} instead of {:?}", (actual.x, actual.y, actual.z), (expected.x, expected.y, expected.z));
+    }
+
+    #[test]
+    fn spheres_report_gap_and_overlap () {
+        let sphere = Shape::Sphere { radius: 1f32 };
+        let a = object_at(0f32, 0f32, 0f32);
+
+        let (normal, depth, point) = collide(&sphere, &a, &sphere, &object_at(2.5f32, 0f32, 0f32), 1f32).unwrap();
+        assert!((depth + 0.5f32).abs() < 1e-4f32, "gap depth {}", depth);
+        assert_close(normal, -Vector::ex(), 1e-4f32);
+        assert_close(point, Vector::new(1.25f32, 0f32, 0f32, 1f32), 1e-4f32);
+
+        let (normal, depth, _) = collide(&sphere, &a, &sphere, &object_at(1.5f32, 0f32, 0f32), 1f32).unwrap();
+        assert!((depth - 0.5f32).abs() < 1e-3f32, "overlap depth {}", depth);
+        assert_close(normal, -Vector::ex(), 1e-3f32);
+
+        assert!(collide(&sphere, &a, &sphere, &object_at(3.5f32, 0f32, 0f32), 1f32).is_none());
+    }
+
+    #[test]
+    fn box_resting_on_the_ground_is_held_at_four_corners () {
+        let shape = Shape::Box { half_extents: Vector::new(1f32, 0.5f32, 2f32, 0f32) };
+        let object = object_at(3f32, 0.49f32, -1f32);
+
+        let (normal, depth, _) = collide_ground(&shape, &object, 0f32, 0.01f32).unwrap();
+        assert_close(normal, Vector::ey(), 1e-6f32);
+        assert!((depth - 0.01f32).abs() < 1e-5f32, "depth {}", depth);
+
+        let points = ground_manifold(&shape, &object, 0f32, 0.01f32);
+        assert!(points.len() == 4, "{} manifold points", points.len());
+
+        for &(depth, point) in &points {
+            assert!((depth - 0.01f32).abs() < 1e-5f32, "depth {}", depth);
+            assert!((point.x - 3f32).abs() == 1f32 && (point.z + 1f32).abs() == 2f32);
+        }
+    }
+
+    #[test]
+    fn rotated_box_lands_on_its_edge () {
+        let shape = Shape::Box { half_extents: Vector::new(1f32, 1f32, 1f32, 0f32) };
+
+        //turned 45 degrees around z, the lowest edge dips 0.05 into the box below
+        let mut a = object_at(0.2f32, 1f32 + ::std::f32::consts::SQRT_2 - 0.05f32, 0f32);
+        a.rotation = Quaternion::from_vector(Vector::ez() * ::std::f32::consts::FRAC_PI_4);
+        let b = object_at(0f32, 0f32, 0f32);
+
+        let (normal, depth, point) = collide(&shape, &a, &shape, &b, 0.01f32).unwrap();
+        assert_close(normal, Vector::ey(), 1e-3f32);
+        assert!((depth - 0.05f32).abs() < 1e-3f32, "depth {}", depth);
+        assert!((point.x - 0.2f32).abs() < 1e-3f32 && (point.y - 0.975f32).abs() < 1e-3f32, "point {:?}", (point.x, point.y));
+
+        let mut manager = ObjectManager::new();
+        let tag_a = manager.push_object_tagged(a.clone());
+        let tag_b = manager.push_object_tagged(b.clone());
+
+        let contact = Contact { a: tag_a, b: Some(tag_b), normal, depth, point };
+        let points = manifold(&shape, &a, &shape, &b, &contact, 0.01f32);
+        assert!(points.len() == 2, "{} manifold points along the edge", points.len());
+    }
+
+    #[test]
+    fn capsule_ends_are_round () {
+        let capsule = Shape::Capsule { radius: 0.5f32, half_length: 1f32 };
+        let sphere = Shape::Sphere { radius: 0.5f32 };
+
+        //upright, the caps reach half_length + radius along y
+        let object = object_at(0f32, 1.4f32, 0f32);
+        let (_, depth, point) = collide_ground(&capsule, &object, 0f32, 0.01f32).unwrap();
+        assert!((depth - 0.1f32).abs() < 1e-5f32, "ground depth {}", depth);
+        assert_close(point, Vector::new(0f32, -0.05f32, 0f32, 1f32), 1e-5f32);
+
+        //a sphere off to the side of the top cap touches it along the diagonal
+        let offset = 0.95f32 / ::std::f32::consts::SQRT_2;
+        let ball = object_at(offset, 1.4f32 + 1f32 + offset, 0f32);
+        let (normal, depth, _) = collide(&sphere, &ball, &capsule, &object, 0.01f32).unwrap();
+        assert!((depth - 0.05f32).abs() < 1e-3f32, "cap depth {}", depth);
+        assert_close(normal, Vector::new(1f32, 1f32, 0f32, 0f32) / ::std::f32::consts::SQRT_2, 1e-3f32);
+
+        //lying along x, the caps stick out sideways and not down
+        let mut lying = object_at(0f32, 0.45f32, 0f32);
+        lying.rotation = Quaternion::from_vector(Vector::ez() * ::std::f32::consts::FRAC_PI_2);
+        let (_, depth, _) = collide_ground(&capsule, &lying, 0f32, 0.01f32).unwrap();
+        assert!((depth - 0.05f32).abs() < 1e-5f32, "lying depth {}", depth);
+
+        let aabb = Aabb::of(&capsule, &lying);
+        assert!((aabb.max.x - 1.5f32).abs() < 1e-5f32 && (aabb.min.x + 1.5f32).abs() < 1e-5f32);
+    }
+}
@@ -0,0 +1,106 @@
+use object::Object;
+use spline::Spline;
+use spline::Bezier;
+use vector::Vector;
+use drone::DroneController;
+use drone::Wrench;
+use pid::Pid;
+
+//what a flight controller asks for during one control step
+#[derive(Clone, Copy)]
+pub struct Command {
+    pub acceleration: Vector,   //desired acceleration of the drone, world frame, without gravity compensation
+    pub wrench:       Wrench,   //thrust and torque for the airframe to allocate
+}
+
+//anything that can fly the drone along a reference trajectory, controllers may keep state (integrators, filters) between calls
+pub trait FlightController {
+    //outer loop, reference trajectory -> desired acceleration
+    fn update_position (&mut self, drone: &Object, reference: &Spline, t: f32, dt: f32) -> Vector;
+
+    //inner loop, desired thrust acceleration (gravity already compensated) and heading -> thrust and torque,
+    //heading is (heading, heading rate) from the reference, None if the reference has no heading profile
+    fn update_attitude (&mut self, drone: &Object, a_target: Vector, heading: Option<(f32, f32)>, dt: f32) -> Wrench;
+}
+
+//plans a bezier from the current state onto the reference merge_time ahead every step, and flies it with the pd attitude controller
+pub struct MergeController {
+    pub merge_time: f32,
+    pub attitude:   DroneController,
+    pub heading:    f32, //only used by AttitudeMode::Geometric, when the reference has no heading profile
+}
+
+#[allow(dead_code)]
+impl MergeController {
+    pub fn new (merge_time: f32, attitude: DroneController, heading: f32) -> MergeController {
+        MergeController {
+            merge_time,
+            attitude,
+            heading,
+        }
+    }
+}
+
+impl FlightController for MergeController {
+    fn update_position (&mut self, drone: &Object, reference: &Spline, t: f32, _dt: f32) -> Vector {
+        let t_merge = (t + self.merge_time) % reference.duration();
+        let (p_merge, v_merge, _) = reference.sample_all(t_merge);
+
+        let mut s2 = Spline::new();
+        s2.push_curve(
+            Bezier::merge(
+                drone.position,
+                drone.velocity,
+                t,
+                p_merge,
+                v_merge,
+                t + self.merge_time
+            )
+        );
+
+        s2.sample_acceleration(t)
+    }
+
+    fn update_attitude (&mut self, drone: &Object, a_target: Vector, heading: Option<(f32, f32)>, _dt: f32) -> Wrench {
+        let (heading, heading_rate) = heading.unwrap_or((self.heading, 0f32));
+
+        self.attitude.command(drone, a_target, heading, heading_rate)
+    }
+}
+
+//cascaded position -> velocity -> attitude controller, follows the reference directly instead of re-planning,
+//the integral terms reject constant disturbances like wind or an off-center payload
+pub struct PidController {
+    pub position: Pid,             //position error -> velocity correction
+    pub velocity: Pid,             //velocity error -> acceleration correction
+    pub attitude: DroneController, //innermost loop
+    pub heading:  f32,             //only used by AttitudeMode::Geometric, when the reference has no heading profile
+}
+
+#[allow(dead_code)]
+impl PidController {
+    pub fn new (position: Pid, velocity: Pid, attitude: DroneController, heading: f32) -> PidController {
+        PidController {
+            position,
+            velocity,
+            attitude,
+            heading,
+        }
+    }
+}
+
+impl FlightController for PidController {
+    fn update_position (&mut self, drone: &Object, reference: &Spline, t: f32, dt: f32) -> Vector {
+        let (p_ref, v_ref, a_ref) = reference.sample_all(t % reference.duration());
+
+        let v_target = v_ref + self.position.update((p_ref - drone.position).to_translation(), dt);
+
+        a_ref + self.velocity.update(v_target - drone.velocity, dt)
+    }
+
+    fn update_attitude (&mut self, drone: &Object, a_target: Vector, heading: Option<(f32, f32)>, _dt: f32) -> Wrench {
+        let (heading, heading_rate) = heading.unwrap_or((self.heading, 0f32));
+
+        self.attitude.command(drone, a_target, heading, heading_rate)
+    }
+}
@@ -0,0 +1,252 @@
+use vector::Vector;
+use object::Object;
+use objectmanager::ObjectManager;
+use objectmanager::ObjectTag;
+use random::Random;
+
+//what a disturbance does while it is active, vectors are in the frame of the disturbance
+#[derive(Clone)]
+pub enum Source {
+    RandomForce  { magnitude: f32 }, //force in a random direction, every step
+    RandomTorque { magnitude: f32 }, //torque around a random axis, every step
+    //N s and N m s, delivered within the first step of every activation
+    Impulse      { impulse: Vector, angular_impulse: Vector },
+    //constant while active
+    Step         { force: Vector, torque: Vector },
+    //on during the first `duration` seconds of every period, counted from the trigger start
+    Periodic     { force: Vector, torque: Vector, period: f32, duration: f32 },
+    //per axis standard deviation after one second, limited in magnitude, restarts from zero on every activation
+    RandomWalk   { force: f32, torque: f32, force_limit: f32, torque_limit: f32 },
+    //per axis standard deviation, white noise through a first order low pass with the bandwidth in Hz
+    Noise        { force: f32, torque: f32, bandwidth: f32 },
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Frame {
+    World,
+    Body, //turns with the object
+}
+
+//object state a disturbance can be triggered by
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Quantity {
+    X,
+    Y, //altitude
+    Z,
+    Speed,
+    VerticalSpeed,
+    AngularSpeed,
+    Tilt, //radians between the body and the world y axis
+}
+
+#[allow(dead_code)]
+impl Quantity {
+    pub fn all () -> [Quantity; 7] {
+        [Quantity::X, Quantity::Y, Quantity::Z, Quantity::Speed, Quantity::VerticalSpeed, Quantity::AngularSpeed, Quantity::Tilt]
+    }
+
+    pub fn name (&self) -> &'static str {
+        match *self {
+            Quantity::X             => "x",
+            Quantity::Y             => "y",
+            Quantity::Z             => "z",
+            Quantity::Speed         => "speed",
+            Quantity::VerticalSpeed => "vertical_speed",
+            Quantity::AngularSpeed  => "angular_speed",
+            Quantity::Tilt          => "tilt",
+        }
+    }
+
+    pub fn from_name (name: &str) -> Option<Quantity> {
+        Quantity::all().iter().find(|quantity| quantity.name() == name).cloned()
+    }
+
+    pub fn of (&self, object: &Object) -> f32 {
+        match *self {
+            Quantity::X             => object.position.x,
+            Quantity::Y             => object.position.y,
+            Quantity::Z             => object.position.z,
+            Quantity::Speed         => Vector::magnitude(object.velocity),
+            Quantity::VerticalSpeed => object.velocity.y,
+            Quantity::AngularSpeed  => Vector::magnitude(object.angular_velocity),
+            Quantity::Tilt          => Vector::angle(object.rotation.to_matrix() * Vector::ey(), Vector::ey()),
+        }
+    }
+}
+
+//quantity > threshold, or quantity < threshold if not above
+#[derive(Clone, Copy)]
+pub struct Condition {
+    pub quantity:  Quantity,
+    pub above:     bool,
+    pub threshold: f32,
+}
+
+#[allow(dead_code)]
+impl Condition {
+    pub fn holds (&self, object: &Object) -> bool {
+        let value = self.quantity.of(object);
+
+        if self.above {
+            value > self.threshold
+        } else {
+            value < self.threshold
+        }
+    }
+}
+
+//when a disturbance is active: start <= t < end and the condition holds
+#[derive(Clone, Copy)]
+pub struct Trigger {
+    pub start:     f32,
+    pub end:       f32, //infinity -> until the end of the run
+    pub condition: Option<Condition>,
+}
+
+#[allow(dead_code)]
+impl Trigger {
+    pub fn always () -> Trigger {
+        Trigger {
+            start:     0f32,
+            end:       f32::INFINITY,
+            condition: None,
+        }
+    }
+
+    pub fn is_active (&self, t: f32, object: &Object) -> bool {
+        if t < self.start || t >= self.end {
+            return false;
+        }
+
+        match self.condition {
+            Some(condition) => condition.holds(object),
+            None => true,
+        }
+    }
+}
+
+//a source attached to an object, several of them on the same object add up
+#[derive(Clone)]
+pub struct Disturbance {
+    pub source:  Source,
+    pub trigger: Trigger,
+    pub frame:   Frame,
+    active:      bool,   //during the previous step
+    force:       Vector, //state of the random walk and the noise, frame of the disturbance
+    torque:      Vector,
+}
+
+#[allow(dead_code)]
+impl Disturbance {
+    //always active, world frame
+    pub fn new (source: Source) -> Disturbance {
+        Disturbance {
+            source,
+            trigger: Trigger::always(),
+            frame:   Frame::World,
+            active:  false,
+            force:   Vector::null(),
+            torque:  Vector::null(),
+        }
+    }
+
+    pub fn with_trigger (mut self, trigger: Trigger) -> Disturbance {
+        self.trigger = trigger;
+        self
+    }
+
+    pub fn with_frame (mut self, frame: Frame) -> Disturbance {
+        self.frame = frame;
+        self
+    }
+
+    //applies the disturbance for the step of length dt ending at t, returns the applied world frame force and torque
+    pub fn apply (&mut self, object_manager: &mut ObjectManager, tag: &ObjectTag, t: f32, dt: f32, rng: &mut Random) -> (Vector, Vector) {
+        let was_active = self.active;
+        self.active = self.trigger.is_active(t, object_manager.get_object(tag));
+
+        if !self.active {
+            return (Vector::null(), Vector::null());
+        }
+
+        let activated = !was_active;
+
+        let (force, torque) = match self.source {
+            Source::RandomForce { magnitude } => {
+                (Vector::random_unitvector(rng) * magnitude, Vector::null())
+            },
+            Source::RandomTorque { magnitude } => {
+                (Vector::null(), Vector::random_unitvector(rng) * magnitude)
+            },
+            Source::Impulse { impulse, angular_impulse } => {
+                if activated {
+                    (impulse / dt, angular_impulse / dt)
+                } else {
+                    (Vector::null(), Vector::null())
+                }
+            },
+            Source::Step { force, torque } => {
+                (force, torque)
+            },
+            Source::Periodic { force, torque, period, duration } => {
+                if (t - self.trigger.start) % period < duration {
+                    (force, torque)
+                } else {
+                    (Vector::null(), Vector::null())
+                }
+            },
+            Source::RandomWalk { force, torque, force_limit, torque_limit } => {
+                if activated {
+                    self.force  = Vector::null();
+                    self.torque = Vector::null();
+                }
+
+                self.force  = clamp(self.force  + gaussian_vector(rng) * (force  * dt.sqrt()), force_limit);
+                self.torque = clamp(self.torque + gaussian_vector(rng) * (torque * dt.sqrt()), torque_limit);
+
+                (self.force, self.torque)
+            },
+            Source::Noise { force, torque, bandwidth } => {
+                //stationary first order gauss-markov process, starts from its stationary distribution
+                let (a, b) = if activated {
+                    (0f32, 1f32)
+                } else {
+                    let a = (-2f32 * ::std::f32::consts::PI * bandwidth * dt).exp();
+                    (a, (1f32 - a * a).sqrt())
+                };
+
+                self.force  = self.force  * a + gaussian_vector(rng) * (force  * b);
+                self.torque = self.torque * a + gaussian_vector(rng) * (torque * b);
+
+                (self.force, self.torque)
+            },
+        };
+
+        let (force, torque) = match self.frame {
+            Frame::World => (force, torque),
+            Frame::Body  => {
+                let local_to_world = object_manager.get_object(tag).rotation.to_matrix();
+                (local_to_world * force, local_to_world * torque)
+            },
+        };
+
+        object_manager.apply_force_torque(force, torque, tag);
+
+        (force, torque)
+    }
+}
+
+fn gaussian_vector (rng: &mut Random) -> Vector {
+    Vector::new(rng.gaussian(), rng.gaussian(), rng.gaussian(), 0f32)
+}
+
+//shortens v to at most limit
+fn clamp (v: Vector, limit: f32) -> Vector {
+    let magnitude = Vector::magnitude(v);
+
+    if magnitude > limit {
+        v * (limit / magnitude)
+    } else {
+        v
+    }
+}
@@ -1,8 +1,11 @@
 use object::Object;
 use vector::Vector;
 use matrix::Matrix;
+use quaternion::Quaternion;
 use objectmanager;
 use objectmanager::ObjectManager;
+use motor::Motor;
+use motor::MotorModel;
 
 
 /* drone convention, topdown view;
@@ -18,60 +21,518 @@ pitch - rotation around z
 yaw - rotation around y
 */
 
-const BETA: f32 = -25f32; //15f32
-const ALPHA: f32 = BETA * BETA / 4f32;
+//alpha - proportional gain, beta - derivative gain (negative), alpha = beta * beta / 4 is critically damped
+#[derive(Clone, Copy)]
+pub struct AxisGains {
+    pub alpha: f32,
+    pub beta:  f32,
+}
+
+#[allow(dead_code)]
+impl AxisGains {
+    pub fn new (alpha: f32, beta: f32) -> AxisGains {
+        AxisGains {
+            alpha,
+            beta,
+        }
+    }
+
+    pub fn critically_damped (beta: f32) -> AxisGains {
+        AxisGains::new(beta * beta / 4f32, beta)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum AttitudeMode {
+    Tilt,      //only tilts local_y towards the target acceleration, yaw is damped but never commanded
+    Geometric, //tracks a full orientation on SO(3), thrust direction plus heading
+}
+
+//heading is the rotation around world y, heading 0 points local_x along world x
+pub fn desired_rotation (a_target: Vector, heading: f32, current: Quaternion) -> Quaternion {
+    let local_y = Vector::normalize(a_target).unwrap_or(Vector::ey());
+    let heading_x = Vector::new(heading.cos(), 0f32, -heading.sin(), 0f32);
+
+    let local_z = match Vector::normalize(Vector::cross(heading_x, local_y)) {
+        Some(local_z) => local_z,
+        None => { //thrust along the heading, heading is undefined so keep the current one
+            let current_x = current.to_matrix().0[0];
+            Vector::normalize(Vector::cross(current_x, local_y)).unwrap_or(Vector::ez())
+        },
+    };
+    let local_x = Vector::cross(local_y, local_z);
+
+    Quaternion::from_matrix(Matrix([
+        local_x,
+        local_y,
+        local_z,
+        Vector::new(0f32, 0f32, 0f32, 1f32),
+    ]))
+}
+
+//what the attitude controller asks the airframe for, local space
+#[derive(Clone, Copy)]
+pub struct Wrench {
+    pub thrust: f32,    //along local_y
+    pub torque: Vector, //x - roll, y - yaw, z - pitch
+}
+
+//attitude controller, turns a target acceleration into thrust and torque, the airframe allocates them to the rotors
+#[derive(Clone, Copy)]
+pub struct DroneController {
+    pub roll:         AxisGains,
+    pub pitch:        AxisGains,
+    pub yaw:          AxisGains,
+    pub thrust_scale: f32,
+    pub mode:         AttitudeMode,
+}
+
+#[allow(dead_code)]
+impl DroneController {
+    pub fn new () -> DroneController {
+        DroneController {
+            roll:         AxisGains::critically_damped(-25f32),
+            pitch:        AxisGains::critically_damped(-25f32),
+            yaw:          AxisGains::critically_damped(-25f32),
+            thrust_scale: 1f32,
+            mode:         AttitudeMode::Tilt,
+        }
+    }
+
+    //wrench for a target acceleration, heading and heading_rate are only used in AttitudeMode::Geometric
+    pub fn command (&self, drone :&Object, a_target: Vector, heading: f32, heading_rate: f32) -> Wrench {
+        match self.mode {
+            AttitudeMode::Tilt => self.get_wrench(drone, a_target),
+            AttitudeMode::Geometric => {
+                let rotation_target = desired_rotation(a_target, heading, drone.rotation);
+                self.get_wrench_rotation(drone, rotation_target, Vector::ey() * heading_rate, a_target)
+            },
+        }
+    }
+
+    pub fn get_wrench (&self, drone :&Object, a_target: Vector) -> Wrench {
+        let local_y :Vector = drone.rotation.to_matrix().0[1];
+
+        let axis_desired = Vector::normalize(
+            Vector::cross(local_y, a_target)
+        ).unwrap_or(Vector::null());
+
+        let angle = Vector::angle(local_y, a_target);
+
+        self.wrench(drone, axis_desired * angle, drone.angular_velocity, a_target)
+    }
+
+    //tracks rotation_target, the error is the rotation from the current to the target orientation,
+    //angular_velocity_target is the world space feed-forward rate (e.g. from a heading profile)
+    pub fn get_wrench_rotation (&self, drone :&Object, rotation_target: Quaternion, angular_velocity_target: Vector, a_target: Vector) -> Wrench {
+        let error = (rotation_target * drone.rotation.conjugate()).to_vector();
+
+        self.wrench(drone, error, drone.angular_velocity - angular_velocity_target, a_target)
+    }
+
+    //error - rotation still to be made, axis * angle in world space
+    fn wrench (&self, drone :&Object, error: Vector, angular_velocity: Vector, a_target: Vector) -> Wrench {
+        let local_to_world = drone.rotation.to_matrix();
+
+        let local_x :Vector = local_to_world.0[0];
+        let local_y :Vector = local_to_world.0[1];
+        let local_z :Vector = local_to_world.0[2];
+
+        let roll_action  = Vector::dot(error, local_x) * self.roll.alpha  + Vector::dot(angular_velocity, local_x) * self.roll.beta;
+        let pitch_action = Vector::dot(error, local_z) * self.pitch.alpha + Vector::dot(angular_velocity, local_z) * self.pitch.beta;
+        let yaw_action   = Vector::dot(error, local_y) * self.yaw.alpha   + Vector::dot(angular_velocity, local_y) * self.yaw.beta;
+
+        Wrench {
+            thrust: Vector::magnitude(a_target) * drone.mass * Vector::cos_angle(local_y, a_target) * self.thrust_scale,
+            torque: Vector::new(roll_action, yaw_action, pitch_action, 0f32),
+        }
+    }
+}
+
+impl Default for DroneController {
+    fn default () -> DroneController {
+        DroneController::new()
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Rotor {
+    pub position: Vector, //local space, relative to the center of mass
+    pub axis:     Vector, //local thrust direction, unit length
+    pub spin:     f32,    //1 -> turns counter clockwise around axis, -1 -> clockwise
+}
+
+#[allow(dead_code)]
+impl Rotor {
+    pub fn new (position: Vector, axis: Vector, spin: f32) -> Rotor {
+        assert!(spin == 1f32 || spin == -1f32, "rotor spin must be 1 or -1");
+
+        Rotor {
+            position: position.to_translation(),
+            axis:     Vector::normalize(axis.to_translation()).expect("rotor axis must not be null"),
+            spin,
+        }
+    }
+
+    //[thrust along local_y, torque x, y, z] per newton of rotor thrust, drag_ratio - drag torque per newton of thrust
+    pub fn effectiveness (&self, drag_ratio: f32) -> [f32; 4] {
+        let torque = Vector::cross(self.position, self.axis) - self.axis * (self.spin * drag_ratio);
+
+        [Vector::dot(self.axis, Vector::ey()), torque.x, torque.y, torque.z]
+    }
+}
+
+//parts of the wrench the allocation can give up independently when the motors saturate
+#[derive(Clone, Copy, PartialEq)]
+pub enum AllocationGroup {
+    Attitude, //roll and pitch torque
+    Yaw,
+    Thrust,   //collective thrust, also used to make room for the others while it isn't allocated yet
+}
+
+//result of fitting a wrench into the motor limits
+pub struct Allocation {
+    pub unconstrained: Vec<f32>, //rotor thrusts of the plain pseudo-inverse, may be outside the motor limits
+    pub thrust:        Vec<f32>, //rotor thrusts within the motor limits
+    pub wrench:        Wrench,   //what thrust actually produces
+    pub saturated:     bool,     //part of the commanded wrench had to be given up
+}
+
+//physical layout of the drone, the control allocation is derived from it
+#[derive(Clone)]
+pub struct Airframe {
+    pub rotors:    Vec<Rotor>,
+    pub motor:     MotorModel, //the same for all rotors
+    pub priority:  [AllocationGroup; 3], //most important first
+    effectiveness: Vec<[f32; 4]>, //one column of the effectiveness matrix per rotor
+    mixer:         Vec<[f32; 4]>, //pseudo-inverse of the effectiveness matrix, one row per rotor
+}
 
 #[allow(dead_code)]
-pub fn get_pwm (drone :&Object, a_target: Vector) -> (f32, f32, f32, f32) {
-    //let beta = -25f32; //15f32
-    //let alpha = beta * beta / 4f32;
-    
-    let local_to_world = drone.rotation.to_matrix();
+impl Airframe {
+    pub fn new (rotors: Vec<Rotor>, motor: MotorModel) -> Airframe {
+        let drag_ratio = motor.drag_coefficient / motor.thrust_coefficient;
+        let effectiveness :Vec<[f32; 4]> = rotors.iter().map(|rotor| rotor.effectiveness(drag_ratio)).collect();
+
+        //pseudo-inverse B^T (B B^T)^-1, B is 4 x rotors and needs full row rank
+        let mut bbt = [[0f32; 4]; 4];
+        for b in &effectiveness {
+            for i in 0 .. 4 {
+                for j in 0 .. 4 {
+                    bbt[i][j] += b[i] * b[j];
+                }
+            }
+        }
+
+        let bbt_inverse = Matrix::from_data(bbt).inverse()
+            .expect("airframe can't produce independent thrust, roll, yaw and pitch")
+            .data();
+
+        let mixer = effectiveness.iter().map(|b| {
+            let mut row = [0f32; 4];
+            for i in 0 .. 4 {
+                for j in 0 .. 4 {
+                    row[i] += bbt_inverse[i][j] * b[j]; //symmetric, rows and columns are the same
+                }
+            }
+            row
+        }).collect();
+
+        Airframe {
+            rotors,
+            motor,
+            priority:      [AllocationGroup::Attitude, AllocationGroup::Yaw, AllocationGroup::Thrust],
+            effectiveness,
+            mixer,
+        }
+    }
+
+    //count rotors evenly spaced on a circle in the local xz plane, the first one at first_angle from local_x towards local_z,
+    //numbered clockwise seen from above, spins alternate starting with clockwise
+    pub fn ring (count: usize, arm_length: f32, first_angle: f32, motor: MotorModel) -> Airframe {
+        let rotors = (0 .. count).map(|n| {
+            let angle = first_angle - n as f32 * 2f32 * ::std::f32::consts::PI / count as f32;
+            let position = Vector::new(angle.cos(), 0f32, angle.sin(), 0f32) * arm_length;
+
+            Rotor::new(position, Vector::ey(), if n % 2 == 0 { -1f32 } else { 1f32 })
+        }).collect();
 
-    let derivative_action = drone.angular_velocity * BETA;
-    
-    let local_x :Vector = local_to_world.0[0];
-    let local_y :Vector = local_to_world.0[1];
-    let local_z :Vector = local_to_world.0[2];
+        Airframe::new(rotors, motor)
+    }
 
-    let axis_desired = Vector::normalize(
-        Vector::cross(local_y, a_target)
-    ).unwrap_or(Vector::null());
+    //the layout of the convention above
+    pub fn quad_x (arm_length: f32, motor: MotorModel) -> Airframe {
+        Airframe::ring(4, arm_length, ::std::f32::consts::FRAC_PI_4, motor)
+    }
 
-    let angle = Vector::angle(local_y, a_target);
+    //rotor 1 on local_x
+    pub fn quad_plus (arm_length: f32, motor: MotorModel) -> Airframe {
+        Airframe::ring(4, arm_length, 0f32, motor)
+    }
 
-    let proportional_action = axis_desired * angle * ALPHA;
+    pub fn hexa (arm_length: f32, motor: MotorModel) -> Airframe {
+        Airframe::ring(6, arm_length, ::std::f32::consts::PI / 6f32, motor)
+    }
 
-    let total_action = derivative_action + proportional_action;
-    
-    let roll_action  = Vector::dot(total_action, local_x);
-    let pitch_action = Vector::dot(total_action, local_z);
-    let yaw_action   = Vector::dot(total_action, local_y);
+    pub fn octo (arm_length: f32, motor: MotorModel) -> Airframe {
+        Airframe::ring(8, arm_length, ::std::f32::consts::PI / 8f32, motor)
+    }
 
-    let thrust = Vector::magnitude(a_target) * drone.mass * Vector::cos_angle(local_y, a_target);
+    //x8, a quad_x with a second rotor below each one, spinning the other way, spacing is the vertical distance of the pair
+    pub fn coaxial (arm_length: f32, spacing: f32, motor: MotorModel) -> Airframe {
+        let quad = Airframe::quad_x(arm_length, motor);
+        let mut rotors = Vec::<Rotor>::new();
 
-    (
-        (thrust - roll_action + pitch_action + yaw_action) * 0.25f32,
-        (thrust + roll_action + pitch_action - yaw_action) * 0.25f32,
-        (thrust + roll_action - pitch_action + yaw_action) * 0.25f32,
-        (thrust - roll_action - pitch_action - yaw_action) * 0.25f32,
-    )
+        for rotor in &quad.rotors {
+            rotors.push(Rotor::new(rotor.position + Vector::ey() * (spacing * 0.5f32), rotor.axis, rotor.spin));
+        }
+        for rotor in &quad.rotors {
+            rotors.push(Rotor::new(rotor.position - Vector::ey() * (spacing * 0.5f32), rotor.axis, -rotor.spin));
+        }
+
+        Airframe::new(rotors, motor)
+    }
+
+    pub fn motors (&self) -> Vec<Motor> {
+        vec![Motor::new(self.motor); self.rotors.len()]
+    }
+
+    //thrust per rotor (newton, unclamped) that produces the wrench, least squares if there are more rotors than needed
+    pub fn mix (&self, wrench: Wrench) -> Vec<f32> {
+        let w = [wrench.thrust, wrench.torque.x, wrench.torque.y, wrench.torque.z];
+
+        self.mixer.iter().map(|row| row[0] * w[0] + row[1] * w[1] + row[2] * w[2] + row[3] * w[3]).collect()
+    }
+
+    //wrench produced by the given rotor thrusts, ignores any lateral force of tilted rotors
+    pub fn wrench (&self, thrust: &[f32]) -> Wrench {
+        let mut w = [0f32; 4];
+
+        for (b, &t) in self.effectiveness.iter().zip(thrust.iter()) {
+            for i in 0 .. 4 {
+                w[i] += b[i] * t;
+            }
+        }
+
+        Wrench {
+            thrust: w[0],
+            torque: Vector::new(w[1], w[2], w[3], 0f32),
+        }
+    }
+
+    //fits the wrench into the motor limits, groups are scaled down one after the other in priority order,
+    //every group gets as much as is left after the more important ones
+    pub fn allocate (&self, wrench: Wrench) -> Allocation {
+        let limits = (self.motor.min_thrust(), self.motor.max_thrust());
+
+        let collective = self.mix(Wrench { thrust: 1f32, torque: Vector::null() });
+
+        let mut fixed = vec![0f32; self.rotors.len()];
+        let mut thrust = None::<f32>;
+        let mut saturated = false;
+
+        for group in &self.priority {
+            let part = match *group {
+                AllocationGroup::Attitude => self.mix(Wrench { thrust: 0f32, torque: Vector::new(wrench.torque.x, 0f32, wrench.torque.z, 0f32) }),
+                AllocationGroup::Yaw      => self.mix(Wrench { thrust: 0f32, torque: Vector::new(0f32, wrench.torque.y, 0f32, 0f32) }),
+                AllocationGroup::Thrust   => {
+                    //fixed always fits, so the range exists
+                    let (low, high) = collective_range(&fixed, &collective, limits).unwrap_or((0f32, 0f32));
+                    let t = wrench.thrust.max(low).min(high);
+
+                    saturated = saturated || t != wrench.thrust;
+                    thrust = Some(t);
+                    continue;
+                },
+            };
+
+            let fits = |scale: f32| {
+                let candidate :Vec<f32> = fixed.iter().zip(part.iter()).map(|(f, p)| f + p * scale).collect();
+
+                match thrust {
+                    Some(t) => candidate.iter().zip(collective.iter()).all(|(v, c)| within(v + c * t, limits)),
+                    None => collective_range(&candidate, &collective, limits).is_some(),
+                }
+            };
+
+            let scale = if fits(1f32) {
+                1f32
+            } else {
+                saturated = true;
+
+                let mut low  = 0f32;
+                let mut high = 1f32;
+                for _ in 0 .. 20 {
+                    let middle = (low + high) * 0.5f32;
+                    if fits(middle) {
+                        low = middle;
+                    } else {
+                        high = middle;
+                    }
+                }
+                low
+            };
+
+            for (f, p) in fixed.iter_mut().zip(part.iter()) {
+                *f += p * scale;
+            }
+        }
+
+        let t = thrust.unwrap_or(wrench.thrust);
+        let thrust :Vec<f32> = fixed.iter().zip(collective.iter()).map(|(f, c)| (f + c * t).max(limits.0).min(limits.1)).collect();
+
+        Allocation {
+            unconstrained: self.mix(wrench),
+            wrench:        self.wrench(&thrust),
+            thrust,
+            saturated,
+        }
+    }
+}
+
+const LIMIT_TOLERANCE :f32 = 1e-5f32; //newton
+
+fn within (value: f32, limits: (f32, f32)) -> bool {
+    value >= limits.0 - LIMIT_TOLERANCE && value <= limits.1 + LIMIT_TOLERANCE
+}
+
+//range of collective thrust that keeps fixed + collective * thrust within the limits for every rotor, None if there is none
+fn collective_range (fixed: &[f32], collective: &[f32], limits: (f32, f32)) -> Option<(f32, f32)> {
+    let mut low  = f32::NEG_INFINITY;
+    let mut high = f32::INFINITY;
+
+    for (&f, &c) in fixed.iter().zip(collective.iter()) {
+        if c.abs() < 1e-6f32 { //this rotor doesn't take part in the collective thrust
+            if !within(f, limits) {
+                return None;
+            }
+        } else {
+            let a = (limits.0 - f) / c;
+            let b = (limits.1 - f) / c;
+            low  = low.max(a.min(b));
+            high = high.min(a.max(b));
+        }
+    }
+
+    if low <= high + LIMIT_TOLERANCE {
+        Some((low, high.max(low)))
+    } else {
+        None
+    }
 }
 
+//forces and torques of the spinning rotors
 #[allow(dead_code)]
-pub fn apply_pwm (object_manager: &mut ObjectManager, drone_tag: &objectmanager::ObjectTag , pwm1: f32, pwm2: f32, pwm3: f32, pwm4: f32) {
+pub fn apply_motors (object_manager: &mut ObjectManager, drone_tag: &objectmanager::ObjectTag, airframe: &Airframe, motors: &[Motor]) {
+    assert!(motors.len() == airframe.rotors.len());
+
     let local_to_world :Matrix = object_manager.get_object(drone_tag).rotation.to_matrix();
-        
-    let force_local = Vector::ey() * (pwm1 + pwm2 + pwm3 + pwm4);
-    let torque_local = Vector {
-        x: -pwm1 + pwm2 + pwm3 - pwm4,
-        y:  pwm1 - pwm2 + pwm3 - pwm4,
-        z:  pwm1 + pwm2 - pwm3 - pwm4,
-        w: 0f32,
-    };
-    
+
+    let mut force_local  = Vector::null();
+    let mut torque_local = Vector::null();
+
+    for (rotor, motor) in airframe.rotors.iter().zip(motors.iter()) {
+        let force = rotor.axis * motor.thrust();
+
+        force_local  += force;
+        torque_local += Vector::cross(rotor.position, force) - rotor.axis * (rotor.spin * motor.drag_torque());
+    }
+
     let force  = local_to_world * force_local;
     let torque = local_to_world * torque_local;
 
     object_manager.apply_force_torque(force, torque, drone_tag);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_wrench (actual: Wrench, expected: Wrench) {
+        let error = (actual.thrust - expected.thrust).abs() + Vector::magnitude(actual.torque - expected.torque);
+        assert!(error < 1e-4f32, "wrench {} {:?} instead of {} {:?}", actual.thrust, (actual.torque.x, actual.torque.y, actual.torque.z), expected.thrust, (expected.torque.x, expected.torque.y, expected.torque.z));
+    }
+
+    fn wrench (thrust: f32, x: f32, y: f32, z: f32) -> Wrench {
+        Wrench { thrust, torque: Vector::new(x, y, z, 0f32) }
+    }
+
+    #[test]
+    fn mixing_round_trips () {
+        let motor = MotorModel::new();
+        let airframes = [
+            Airframe::quad_x(1f32, motor),
+            Airframe::quad_plus(1f32, motor),
+            Airframe::hexa(1f32, motor),
+            Airframe::octo(1f32, motor),
+            Airframe::coaxial(1f32, 0.2f32, motor),
+        ];
+
+        for airframe in airframes.iter() {
+            for w in [wrench(20f32, 0f32, 0f32, 0f32), wrench(15f32, 1.5f32, -0.4f32, 0.8f32), wrench(0f32, -2f32, 0.3f32, 0f32)].iter() {
+                assert_wrench(airframe.wrench(&airframe.mix(*w)), *w);
+            }
+        }
+    }
+
+    #[test]
+    fn quad_x_matches_the_original_mixing () {
+        //thrust, roll, yaw and pitch of pwm1 .. pwm4 in the hardwired apply_pwm, drag torque was as large as thrust
+        let original = [
+            wrench(1f32, -1f32,  1f32,  1f32),
+            wrench(1f32,  1f32, -1f32,  1f32),
+            wrench(1f32,  1f32,  1f32, -1f32),
+            wrench(1f32, -1f32, -1f32, -1f32),
+        ];
+
+        let motor = MotorModel::new();
+        assert!(motor.drag_coefficient == motor.thrust_coefficient);
+
+        let airframe = Airframe::quad_x(::std::f32::consts::SQRT_2, motor);
+
+        for (n, expected) in original.iter().enumerate() {
+            let mut thrust = vec![0f32; 4];
+            thrust[n] = 1f32;
+
+            assert_wrench(airframe.wrench(&thrust), *expected);
+        }
+    }
+
+    #[test]
+    fn allocation_stays_within_the_motor_limits () {
+        let motor = MotorModel::new();
+        let airframe = Airframe::quad_x(::std::f32::consts::SQRT_2, motor);
+
+        for &thrust in [0f32, 10f32, 25f32, 39f32, 60f32].iter() {
+            for &torque in [0f32, 1f32, 5f32, 20f32].iter() {
+                let allocation = airframe.allocate(wrench(thrust, torque, -0.5f32 * torque, 0.8f32 * torque));
+
+                for &t in &allocation.thrust {
+                    assert!(t >= motor.min_thrust() && t <= motor.max_thrust(), "rotor thrust {} outside {} ..= {}", t, motor.min_thrust(), motor.max_thrust());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn allocation_trades_collective_for_attitude () {
+        let airframe = Airframe::quad_x(::std::f32::consts::SQRT_2, MotorModel::new());
+
+        //the most loaded rotor gets 39 / 4 + 1.5, 34 / 4 + 1.5 is its maximum thrust
+        let allocation = airframe.allocate(wrench(39f32, 3f32, 0f32, 3f32));
+
+        assert!(allocation.saturated);
+        assert_wrench(allocation.wrench, wrench(34f32, 3f32, 0f32, 3f32));
+    }
+
+    #[test]
+    fn allocation_inside_the_envelope_is_exact () {
+        let airframe = Airframe::quad_x(::std::f32::consts::SQRT_2, MotorModel::new());
+
+        let command = wrench(20f32, 1f32, 0.2f32, -0.5f32);
+        let allocation = airframe.allocate(command);
+
+        assert!(!allocation.saturated);
+        assert_wrench(allocation.wrench, command);
+    }
+}
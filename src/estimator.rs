@@ -0,0 +1,575 @@
+use vector::Vector;
+use quaternion::Quaternion;
+use object::Object;
+use scheduler::Scheduler;
+use scheduler::Task;
+use sensor::SensorKind;
+use sensor::NoiseModel;
+use sensor::Sensors;
+use sensor::Readings;
+
+const MIN_VARIANCE        :f32 = 1e-6f32; //keeps the kalman filter from trusting perfect sensors completely
+const ACCELERATION_FILTER :f32 = 0.2f32;  //seconds, time constant of the gps acceleration of the complementary filter
+
+//what the flight controller believes about the drone, same frames as Object
+#[derive(Clone, Copy)]
+pub struct Estimate {
+    pub position:         Vector,
+    pub velocity:         Vector,
+    pub rotation:         Quaternion,
+    pub angular_velocity: Vector, //world space
+}
+
+#[allow(dead_code)]
+impl Estimate {
+    pub fn from_object (object: &Object) -> Estimate {
+        Estimate {
+            position:         object.position,
+            velocity:         object.velocity,
+            rotation:         object.rotation,
+            angular_velocity: object.angular_velocity,
+        }
+    }
+
+    //the object with its state replaced by the estimate, mass, inertia and the rest stay
+    pub fn apply (&self, object: &Object) -> Object {
+        let mut perceived = object.clone();
+        perceived.position         = self.position;
+        perceived.velocity         = self.velocity;
+        perceived.rotation         = self.rotation;
+        perceived.angular_velocity = self.angular_velocity;
+        perceived
+    }
+}
+
+//turns sensor readings into an estimate of the drone state
+pub trait Estimator {
+    //starts from the true state, like a drone that gets aligned on the ground before takeoff
+    fn reset (&mut self, object: &Object);
+
+    //advances the estimate by dt using the newest readings, then corrects it with the samples that just arrived,
+    //readings already contain the arrivals
+    fn update (&mut self, readings: &Readings, arrivals: &[(SensorKind, Vector)], dt: f32);
+
+    fn estimate (&self) -> Estimate;
+}
+
+//gains of the complementary filter, all in 1/s
+#[derive(Clone, Copy)]
+pub struct ComplementaryGains {
+    pub tilt:     f32, //pulls the measured specific force towards the one expected from the gps velocity
+    pub heading:  f32, //pulls the horizontal magnetic field towards the known one
+    pub bias:     f32, //integral term, learns the gyro bias
+    pub position: f32, //towards gps position
+    pub velocity: f32, //towards gps velocity
+    pub altitude: f32, //towards the barometer
+}
+
+#[allow(dead_code)]
+impl ComplementaryGains {
+    pub fn new () -> ComplementaryGains {
+        ComplementaryGains {
+            tilt:     0.3f32,
+            heading:  0.5f32,
+            bias:     0.05f32,
+            position: 0.2f32,
+            velocity: 2f32,
+            altitude: 1f32,
+        }
+    }
+}
+
+impl Default for ComplementaryGains {
+    fn default () -> ComplementaryGains {
+        ComplementaryGains::new()
+    }
+}
+
+//integrates the gyro and the accelerometer, and slowly pulls the result towards the absolute sensors
+pub struct ComplementaryFilter {
+    pub gains:          ComplementaryGains,
+    pub gravity:        Vector,
+    pub magnetic_field: Vector,
+    estimate:           Estimate,
+    gyro_bias:          Vector, //body frame
+    acceleration:       Vector, //world frame, from differentiating the gps velocity
+    gps_velocity:       Option<Vector>, //previous sample
+    last_arrival:       [f32; 6], //time of the previous sample of every sensor kind
+    received:           [bool; 6],
+    t:                  f32,
+}
+
+#[allow(dead_code)]
+impl ComplementaryFilter {
+    pub fn new (gains: ComplementaryGains, gravity: Vector, magnetic_field: Vector) -> ComplementaryFilter {
+        ComplementaryFilter {
+            gains,
+            gravity,
+            magnetic_field,
+            estimate:       Estimate::from_object(&Object::new()),
+            gyro_bias:      Vector::null(),
+            acceleration:   Vector::null(),
+            gps_velocity:   None,
+            last_arrival:   [0f32; 6],
+            received:       [false; 6],
+            t:              0f32,
+        }
+    }
+
+    //fraction of the way towards a sample that came interval after the previous one
+    fn blend (gain: f32, interval: f32) -> f32 {
+        1f32 - (-gain * interval).exp()
+    }
+}
+
+impl Estimator for ComplementaryFilter {
+    fn reset (&mut self, object: &Object) {
+        self.estimate     = Estimate::from_object(object);
+        self.gyro_bias    = Vector::null();
+        self.acceleration = Vector::null();
+        self.gps_velocity = None;
+        self.last_arrival = [0f32; 6];
+        self.received     = [false; 6];
+        self.t            = 0f32;
+    }
+
+    fn update (&mut self, readings: &Readings, arrivals: &[(SensorKind, Vector)], dt: f32) {
+        self.t += dt;
+
+        for &(kind, _) in arrivals {
+            self.received[kind as usize] = true;
+        }
+
+        if self.received[SensorKind::Accelerometer as usize] && self.received[SensorKind::Gyroscope as usize] {
+            let local_to_world = self.estimate.rotation.to_matrix();
+            let rate           = readings.gyroscope - self.gyro_bias;
+            let specific_force = local_to_world * readings.accelerometer;
+
+            //rotation that brings the measured directions onto the expected ones, world frame
+            let mut correction = Vector::null();
+
+            if let (Some(measured), Some(expected)) = (Vector::normalize(specific_force), Vector::normalize(self.acceleration - self.gravity)) {
+                correction += Vector::cross(measured, expected) * self.gains.tilt;
+            }
+
+            if self.received[SensorKind::Magnetometer as usize] {
+                let horizontal = |v: Vector| v - Vector::ey() * v.y;
+
+                let measured = Vector::normalize(horizontal(local_to_world * readings.magnetometer));
+                let expected = Vector::normalize(horizontal(self.magnetic_field));
+
+                if let (Some(measured), Some(expected)) = (measured, expected) {
+                    correction += Vector::cross(measured, expected) * self.gains.heading;
+                }
+            }
+
+            self.gyro_bias -= local_to_world.transpose() * correction * (self.gains.bias * dt);
+
+            self.estimate.angular_velocity = local_to_world * rate;
+            self.estimate.rotation = Quaternion::from_vector((self.estimate.angular_velocity + correction) * dt) * self.estimate.rotation;
+            self.estimate.rotation.normalize();
+
+            let acceleration = specific_force + self.gravity;
+            self.estimate.position += self.estimate.velocity * dt + acceleration * (0.5f32 * dt * dt);
+            self.estimate.velocity += acceleration * dt;
+        }
+
+        for &(kind, reading) in arrivals {
+            let interval = self.t - self.last_arrival[kind as usize];
+            self.last_arrival[kind as usize] = self.t;
+
+            match kind {
+                SensorKind::GpsPosition => {
+                    self.estimate.position += (reading - self.estimate.position) * ComplementaryFilter::blend(self.gains.position, interval);
+                },
+                SensorKind::GpsVelocity => {
+                    self.estimate.velocity += (reading - self.estimate.velocity) * ComplementaryFilter::blend(self.gains.velocity, interval);
+
+                    if let Some(previous) = self.gps_velocity {
+                        if interval > 0f32 {
+                            let acceleration = (reading - previous) / interval;
+                            self.acceleration += (acceleration - self.acceleration) * (1f32 - (-interval / ACCELERATION_FILTER).exp());
+                        }
+                    }
+                    self.gps_velocity = Some(reading);
+                },
+                SensorKind::Barometer => {
+                    self.estimate.position.y += (reading.x - self.estimate.position.y) * ComplementaryFilter::blend(self.gains.altitude, interval);
+                },
+                _ => {},
+            }
+        }
+    }
+
+    fn estimate (&self) -> Estimate {
+        self.estimate
+    }
+}
+
+//error state indices of the kalman filter
+const STATES     :usize = 15;
+const POSITION   :usize = 0;
+const VELOCITY   :usize = 3;
+const ATTITUDE   :usize = 6;  //small rotation in the body frame, true = estimate * exp(error)
+const GYRO_BIAS  :usize = 9;
+const ACCEL_BIAS :usize = 12;
+
+type Covariance = [[f32; STATES]; STATES];
+
+//error state extended kalman filter over position, velocity, attitude and the gyro and accelerometer biases,
+//the imu drives the prediction, gps, barometer and magnetometer correct it one axis at a time,
+//the noise is taken from the sensor models
+pub struct Ekf {
+    pub gravity:        Vector,
+    pub magnetic_field: Vector,
+    models:             [NoiseModel; 6], //by SensorKind
+    periods:            [f32; 6],        //sample periods, by SensorKind
+    estimate:           Estimate,
+    gyro_bias:          Vector, //body frame
+    accelerometer_bias: Vector, //body frame
+    covariance:         Covariance,
+    received:           [bool; 6],
+}
+
+#[allow(dead_code)]
+impl Ekf {
+    pub fn new (sensors: &Sensors, scheduler: &Scheduler, gravity: Vector) -> Ekf {
+        let mut models  = [NoiseModel::new(); 6];
+        let mut periods = [scheduler.dt; 6];
+
+        for sensor in &sensors.sensors {
+            models[sensor.kind as usize]  = sensor.model;
+            periods[sensor.kind as usize] = scheduler.period(Task::Sensor(sensor.kind));
+        }
+
+        let mut ekf = Ekf {
+            gravity,
+            magnetic_field:     sensors.magnetic_field,
+            models,
+            periods,
+            estimate:           Estimate::from_object(&Object::new()),
+            gyro_bias:          Vector::null(),
+            accelerometer_bias: Vector::null(),
+            covariance:         [[0f32; STATES]; STATES],
+            received:           [false; 6],
+        };
+
+        ekf.reset_covariance();
+        ekf
+    }
+
+    //how far the gyro and accelerometer biases are, the state itself starts aligned
+    fn reset_covariance (&mut self) {
+        self.covariance = [[0f32; STATES]; STATES];
+
+        let gyro_bias  = self.models[SensorKind::Gyroscope as usize].bias;
+        let accel_bias = self.models[SensorKind::Accelerometer as usize].bias;
+
+        for i in 0 .. 3 {
+            self.covariance[POSITION + i][POSITION + i]     = MIN_VARIANCE;
+            self.covariance[VELOCITY + i][VELOCITY + i]     = MIN_VARIANCE;
+            self.covariance[ATTITUDE + i][ATTITUDE + i]     = MIN_VARIANCE;
+            self.covariance[GYRO_BIAS + i][GYRO_BIAS + i]   = (gyro_bias * gyro_bias).max(MIN_VARIANCE);
+            self.covariance[ACCEL_BIAS + i][ACCEL_BIAS + i] = (accel_bias * accel_bias).max(MIN_VARIANCE);
+        }
+    }
+
+    //variance of one sample of a sensor whose bias isn't part of the state
+    fn measurement_variance (&self, kind: SensorKind) -> f32 {
+        let model = self.models[kind as usize];
+
+        (model.noise * model.noise + model.bias * model.bias + model.quantization * model.quantization / 12f32).max(MIN_VARIANCE)
+    }
+
+    fn predict (&mut self, readings: &Readings, dt: f32) {
+        let local_to_world = self.estimate.rotation.to_matrix();
+        let rate           = readings.gyroscope - self.gyro_bias;
+        let specific_force = readings.accelerometer - self.accelerometer_bias;
+        let acceleration   = local_to_world * specific_force + self.gravity;
+
+        self.estimate.position += self.estimate.velocity * dt + acceleration * (0.5f32 * dt * dt);
+        self.estimate.velocity += acceleration * dt;
+        self.estimate.rotation = self.estimate.rotation * Quaternion::from_vector(rate * dt);
+        self.estimate.rotation.normalize();
+        self.estimate.angular_velocity = local_to_world * rate;
+
+        //linearized error dynamics, first order
+        let mut transition = identity();
+        let axes = [Vector::ex(), Vector::ey(), Vector::ez()];
+
+        for j in 0 .. 3 {
+            let velocity_by_attitude = local_to_world * Vector::cross(specific_force, axes[j]) * -dt;
+            let velocity_by_bias     = local_to_world * axes[j] * -dt;
+            let attitude_by_attitude = Vector::cross(rate, axes[j]) * -dt;
+
+            transition[POSITION + j][VELOCITY + j]  = dt;
+            transition[ATTITUDE + j][GYRO_BIAS + j] = -dt;
+
+            for i in 0 .. 3 {
+                transition[VELOCITY + i][ATTITUDE + j]   = velocity_by_attitude[i];
+                transition[VELOCITY + i][ACCEL_BIAS + j] = velocity_by_bias[i];
+                transition[ATTITUDE + i][ATTITUDE + j]  += attitude_by_attitude[i];
+            }
+        }
+
+        self.covariance = multiply(&multiply(&transition, &self.covariance), &transpose(&transition));
+
+        //white noise densities from the per sample noise and the sample period, random walks for the biases
+        let accelerometer = self.models[SensorKind::Accelerometer as usize];
+        let gyroscope     = self.models[SensorKind::Gyroscope as usize];
+
+        let velocity_noise   = (accelerometer.noise * accelerometer.noise * self.periods[SensorKind::Accelerometer as usize]).max(MIN_VARIANCE) * dt;
+        let attitude_noise   = (gyroscope.noise * gyroscope.noise * self.periods[SensorKind::Gyroscope as usize]).max(MIN_VARIANCE) * dt;
+        let gyro_bias_noise  = (gyroscope.bias_walk * gyroscope.bias_walk).max(MIN_VARIANCE) * dt;
+        let accel_bias_noise = (accelerometer.bias_walk * accelerometer.bias_walk).max(MIN_VARIANCE) * dt;
+
+        for i in 0 .. 3 {
+            self.covariance[VELOCITY + i][VELOCITY + i]     += velocity_noise;
+            self.covariance[ATTITUDE + i][ATTITUDE + i]     += attitude_noise;
+            self.covariance[GYRO_BIAS + i][GYRO_BIAS + i]   += gyro_bias_noise;
+            self.covariance[ACCEL_BIAS + i][ACCEL_BIAS + i] += accel_bias_noise;
+        }
+    }
+
+    //scalar measurement, innovation = measured - predicted, h maps the error state onto it
+    fn correct (&mut self, h: &[f32; STATES], innovation: f32, variance: f32) {
+        let mut ph = [0f32; STATES];
+        for (p, row) in ph.iter_mut().zip(self.covariance.iter()) {
+            *p = row.iter().zip(h.iter()).map(|(c, h)| c * h).sum();
+        }
+
+        let mut s = variance;
+        for i in 0 .. STATES {
+            s += h[i] * ph[i];
+        }
+
+        let mut error = [0f32; STATES];
+        for i in 0 .. STATES {
+            let gain = ph[i] / s;
+            error[i] = gain * innovation;
+
+            for (c, p) in self.covariance[i].iter_mut().zip(ph.iter()) {
+                *c -= gain * p;
+            }
+        }
+
+        //rounding makes it drift away from symmetric
+        for i in 0 .. STATES {
+            for j in 0 .. i {
+                let mean = 0.5f32 * (self.covariance[i][j] + self.covariance[j][i]);
+                self.covariance[i][j] = mean;
+                self.covariance[j][i] = mean;
+            }
+        }
+
+        self.inject(&error);
+    }
+
+    //moves the error into the estimate, the error state is zero again afterwards
+    fn inject (&mut self, error: &[f32; STATES]) {
+        let part = |start: usize| Vector::new(error[start], error[start + 1], error[start + 2], 0f32);
+
+        self.estimate.position += part(POSITION);
+        self.estimate.velocity += part(VELOCITY);
+        self.estimate.rotation = self.estimate.rotation * Quaternion::from_vector(part(ATTITUDE));
+        self.estimate.rotation.normalize();
+        self.gyro_bias          += part(GYRO_BIAS);
+        self.accelerometer_bias += part(ACCEL_BIAS);
+    }
+
+    //one scalar update per axis of a sample that measures part of the state directly
+    fn correct_direct (&mut self, kind: SensorKind, start: usize, measured: Vector, predicted: Vector, axes: usize) {
+        let variance = self.measurement_variance(kind);
+
+        for i in 0 .. axes {
+            let mut h = [0f32; STATES];
+            h[start + i] = 1f32;
+
+            self.correct(&h, measured[i] - predicted[i], variance);
+        }
+    }
+
+    fn correct_magnetometer (&mut self, measured: Vector) {
+        let variance = self.measurement_variance(SensorKind::Magnetometer);
+        let axes = [Vector::ex(), Vector::ey(), Vector::ez()];
+
+        for i in 0 .. 3 {
+            //body frame field, a body frame rotation error changes it by predicted x error
+            let predicted = self.estimate.rotation.to_matrix().transpose() * self.magnetic_field;
+
+            let mut h = [0f32; STATES];
+            for j in 0 .. 3 {
+                h[ATTITUDE + j] = Vector::cross(predicted, axes[j])[i];
+            }
+
+            self.correct(&h, measured[i] - predicted[i], variance);
+        }
+    }
+}
+
+impl Estimator for Ekf {
+    fn reset (&mut self, object: &Object) {
+        self.estimate           = Estimate::from_object(object);
+        self.gyro_bias          = Vector::null();
+        self.accelerometer_bias = Vector::null();
+        self.received           = [false; 6];
+        self.reset_covariance();
+    }
+
+    fn update (&mut self, readings: &Readings, arrivals: &[(SensorKind, Vector)], dt: f32) {
+        for &(kind, _) in arrivals {
+            self.received[kind as usize] = true;
+        }
+
+        if self.received[SensorKind::Accelerometer as usize] && self.received[SensorKind::Gyroscope as usize] {
+            self.predict(readings, dt);
+        }
+
+        for &(kind, reading) in arrivals {
+            match kind {
+                SensorKind::GpsPosition => {
+                    let predicted = self.estimate.position;
+                    self.correct_direct(kind, POSITION, reading, predicted, 3);
+                },
+                SensorKind::GpsVelocity => {
+                    let predicted = self.estimate.velocity;
+                    self.correct_direct(kind, VELOCITY, reading, predicted, 3);
+                },
+                SensorKind::Barometer => {
+                    let predicted = Vector::new(self.estimate.position.y, 0f32, 0f32, 0f32);
+                    self.correct_direct(kind, POSITION + 1, reading, predicted, 1);
+                },
+                SensorKind::Magnetometer => {
+                    self.correct_magnetometer(reading);
+                },
+                _ => {},
+            }
+        }
+    }
+
+    fn estimate (&self) -> Estimate {
+        self.estimate
+    }
+}
+
+fn identity () -> Covariance {
+    let mut m = [[0f32; STATES]; STATES];
+    for (i, row) in m.iter_mut().enumerate() {
+        row[i] = 1f32;
+    }
+    m
+}
+
+fn multiply (a: &Covariance, b: &Covariance) -> Covariance {
+    let mut m = [[0f32; STATES]; STATES];
+    for i in 0 .. STATES {
+        for k in 0 .. STATES {
+            if a[i][k] == 0f32 {
+                continue; //the transition is mostly empty
+            }
+            for j in 0 .. STATES {
+                m[i][j] += a[i][k] * b[k][j];
+            }
+        }
+    }
+    m
+}
+
+fn transpose (a: &Covariance) -> Covariance {
+    let mut m = [[0f32; STATES]; STATES];
+    for i in 0 .. STATES {
+        for j in 0 .. STATES {
+            m[i][j] = a[j][i];
+        }
+    }
+    m
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use random::Random;
+    use sensor::DEFAULT_MAGNETIC_FIELD;
+
+    const DT :f32 = 0.01f32;
+
+    fn ekf (models: &[(SensorKind, NoiseModel)]) -> Ekf {
+        let sensors = Sensors::new(models, DEFAULT_MAGNETIC_FIELD, Random::new(1));
+        let mut ekf = Ekf::new(&sensors, &Scheduler::new(DT), Vector::ey() * -10f32);
+
+        let mut object = Object::new();
+        object.position = Vector::new(0f32, 1f32, 0f32, 1f32);
+        ekf.reset(&object);
+        ekf
+    }
+
+    //what a level drone hovering at (0, 1, 0) reads, through a gyro with a constant bias
+    fn hover_readings (gyro_bias: Vector) -> Readings {
+        let mut readings = Readings::new();
+        readings.set(SensorKind::Accelerometer, Vector::ey() * 10f32);
+        readings.set(SensorKind::Gyroscope,     gyro_bias);
+        readings.set(SensorKind::Barometer,     Vector::new(1f32, 0f32, 0f32, 0f32));
+        readings.set(SensorKind::GpsPosition,   Vector::new(0f32, 1f32, 0f32, 1f32));
+        readings.set(SensorKind::GpsVelocity,   Vector::null());
+        readings.set(SensorKind::Magnetometer,  DEFAULT_MAGNETIC_FIELD);
+        readings
+    }
+
+    fn arrivals (readings: &Readings, kinds: &[SensorKind]) -> Vec<(SensorKind, Vector)> {
+        kinds.iter().map(|&kind| {
+            let reading = match kind {
+                SensorKind::Accelerometer => readings.accelerometer,
+                SensorKind::Gyroscope     => readings.gyroscope,
+                SensorKind::Barometer     => Vector::new(readings.barometer, 0f32, 0f32, 0f32),
+                SensorKind::GpsPosition   => readings.gps_position,
+                SensorKind::GpsVelocity   => readings.gps_velocity,
+                SensorKind::Magnetometer  => readings.magnetometer,
+            };
+            (kind, reading)
+        }).collect()
+    }
+
+    #[test]
+    fn hover_converges_and_learns_the_gyro_bias () {
+        let mut gyroscope = NoiseModel::new();
+        gyroscope.bias = 0.02f32;
+
+        let mut ekf = ekf(&[(SensorKind::Gyroscope, gyroscope)]);
+
+        let gyro_bias = Vector::new(0.01f32, -0.015f32, 0.02f32, 0f32);
+        let readings = hover_readings(gyro_bias);
+        let arrivals = arrivals(&readings, &SensorKind::all());
+
+        for _ in 0 .. 6000 {
+            ekf.update(&readings, &arrivals, DT);
+        }
+
+        let estimate = ekf.estimate();
+        let attitude_error = Vector::magnitude(estimate.rotation.to_vector());
+        let position_error = Vector::magnitude(estimate.position - Vector::new(0f32, 1f32, 0f32, 1f32));
+        let bias_error     = Vector::magnitude(ekf.gyro_bias - gyro_bias);
+
+        assert!(attitude_error < 0.01f32, "attitude off by {} rad", attitude_error);
+        assert!(position_error < 0.05f32, "position off by {}", position_error);
+        assert!(bias_error < 2e-3f32, "gyro bias off by {}", bias_error);
+    }
+
+    #[test]
+    fn magnetometer_corrects_the_heading () {
+        let mut ekf = ekf(&[]);
+
+        //told the heading is uncertain, starts 0.3 rad off
+        ekf.estimate.rotation = Quaternion::from_vector(Vector::ey() * 0.3f32);
+        ekf.covariance[ATTITUDE + 1][ATTITUDE + 1] = 0.3f32 * 0.3f32;
+
+        let readings = hover_readings(Vector::null());
+        let arrivals = arrivals(&readings, &[SensorKind::Accelerometer, SensorKind::Gyroscope, SensorKind::Magnetometer]);
+
+        for _ in 0 .. 500 {
+            ekf.update(&readings, &arrivals, DT);
+        }
+
+        let heading_error = Vector::magnitude(ekf.estimate().rotation.to_vector());
+        assert!(heading_error < 0.01f32, "heading off by {} rad", heading_error);
+    }
+}
@@ -0,0 +1,283 @@
+use vector::Vector;
+use recorder::Record;
+
+//what counts as tracking and which limits a flight has to stay within, None -> not checked
+#[derive(Clone)]
+pub struct Criteria {
+    pub band:                        f32, //position error below which the drone counts as tracking
+    pub force_threshold:             f32, //disturbance force magnitude above which a disturbance event starts, scenarios checking max_convergence_time have to set it
+    pub torque_threshold:            f32, //same for the disturbance torque
+    pub max_position_rms:            Option<f32>,
+    pub max_position_error:          Option<f32>,
+    pub max_velocity_rms:            Option<f32>,
+    pub max_velocity_error:          Option<f32>,
+    pub max_settling_time:           Option<f32>,
+    pub max_convergence_time:        Option<f32>,
+    pub max_control_effort:          Option<f32>,
+    pub max_saturation_time:         Option<f32>,
+    pub max_estimation_position_rms: Option<f32>,
+    pub max_estimation_attitude_rms: Option<f32>, //radians
+    pub max_collisions:              Option<usize>,
+    pub min_clearance:               Option<f32>, //to every shape and the ground
+}
+
+#[allow(dead_code)]
+impl Criteria {
+    pub fn new () -> Criteria {
+        Criteria {
+            band:                        0.25f32,
+            force_threshold:             0f32,
+            torque_threshold:            0f32,
+            max_position_rms:            None,
+            max_position_error:          None,
+            max_velocity_rms:            None,
+            max_velocity_error:          None,
+            max_settling_time:           None,
+            max_convergence_time:        None,
+            max_control_effort:          None,
+            max_saturation_time:         None,
+            max_estimation_position_rms: None,
+            max_estimation_attitude_rms: None,
+            max_collisions:              None,
+            min_clearance:               None,
+        }
+    }
+}
+
+impl Default for Criteria {
+    fn default () -> Criteria {
+        Criteria::new()
+    }
+}
+
+pub struct Metrics {
+    pub duration:                f32,
+    pub position_rms:            f32,
+    pub position_max:            f32,
+    pub velocity_rms:            f32,
+    pub velocity_max:            f32,
+    pub settling_time:           f32, //time after which the position error stays within the band
+    pub disturbance_events:      usize,
+    pub convergence_time_max:    f32, //longest time from the start of a disturbance event until it is over and the drone is back within the band
+    pub control_effort:          f32, //integral of the summed pwm
+    pub saturation_time:         f32, //time during which the allocation could not produce the commanded wrench
+    pub estimation_position_rms: f32, //estimated against true state, 0 when the controller sees the true state
+    pub estimation_velocity_rms: f32,
+    pub estimation_attitude_rms: f32, //radians
+    pub collisions:              usize, //times the drone started touching something
+    pub min_clearance:           f32,   //negative -> the drone overlapped something, inf if it never got near anything
+}
+
+#[allow(dead_code)]
+impl Metrics {
+    pub fn print (&self) {
+        println!("duration:             {}", self.duration);
+        println!("position error rms:   {}", self.position_rms);
+        println!("position error max:   {}", self.position_max);
+        println!("velocity error rms:   {}", self.velocity_rms);
+        println!("velocity error max:   {}", self.velocity_max);
+        println!("settling time:        {}", self.settling_time);
+        println!("disturbance events:   {}", self.disturbance_events);
+        println!("convergence time max: {}", self.convergence_time_max);
+        println!("control effort:       {}", self.control_effort);
+        println!("saturation time:      {}", self.saturation_time);
+        println!("estimation pos rms:   {}", self.estimation_position_rms);
+        println!("estimation vel rms:   {}", self.estimation_velocity_rms);
+        println!("estimation att rms:   {}", self.estimation_attitude_rms);
+        println!("collisions:           {}", self.collisions);
+        println!("min clearance:        {}", self.min_clearance);
+    }
+
+    //returns a description of every violated limit, empty -> pass
+    pub fn check (&self, criteria: &Criteria) -> Vec<String> {
+        let mut failures = Vec::<String>::new();
+
+        {
+            let mut check = |name: &str, value: f32, limit: Option<f32>| {
+                if let Some(limit) = limit {
+                    if value.is_nan() || value > limit { //a diverged run fails too
+                        failures.push(format!("{} {} exceeds {}", name, value, limit));
+                    }
+                }
+            };
+
+            check("position error rms",   self.position_rms,         criteria.max_position_rms);
+            check("position error max",   self.position_max,         criteria.max_position_error);
+            check("velocity error rms",   self.velocity_rms,         criteria.max_velocity_rms);
+            check("velocity error max",   self.velocity_max,         criteria.max_velocity_error);
+            check("settling time",        self.settling_time,        criteria.max_settling_time);
+            check("convergence time max", self.convergence_time_max, criteria.max_convergence_time);
+            check("control effort",       self.control_effort,       criteria.max_control_effort);
+            check("saturation time",      self.saturation_time,      criteria.max_saturation_time);
+            check("estimation pos rms",   self.estimation_position_rms, criteria.max_estimation_position_rms);
+            check("estimation att rms",   self.estimation_attitude_rms, criteria.max_estimation_attitude_rms);
+            check("collisions",           self.collisions as f32,       criteria.max_collisions.map(|limit| limit as f32));
+        }
+
+        if let Some(limit) = criteria.min_clearance {
+            if self.min_clearance.is_nan() || self.min_clearance < limit { //a diverged run fails too
+                failures.push(format!("min clearance {} below {}", self.min_clearance, limit));
+            }
+        }
+
+        failures
+    }
+}
+
+//accumulates metrics record by record, so long runs don't need to keep their records around
+pub struct Evaluator {
+    criteria:                       Criteria,
+    steps:                          usize,
+    t_start:                        Option<f32>,
+    t_last:                         f32,
+    position_square_sum:            f32,
+    position_max:                   f32,
+    velocity_square_sum:            f32,
+    velocity_max:                   f32,
+    last_outside_band:              Option<f32>,
+    disturbed:                      bool,        //disturbance above the threshold during the previous step
+    disturbance_start:              Option<f32>, //start of the event the drone has not recovered from yet
+    disturbance_events:             usize,
+    convergence_max:                f32,
+    control_effort:                 f32,
+    saturation_time:                f32,
+    estimation_position_square_sum: f32,
+    estimation_velocity_square_sum: f32,
+    estimation_attitude_square_sum: f32,
+    touching:                       bool, //during the previous step
+    collisions:                     usize,
+    min_clearance:                  f32,
+}
+
+#[allow(dead_code)]
+impl Evaluator {
+    pub fn new (criteria: &Criteria) -> Evaluator {
+        Evaluator {
+            criteria:                       criteria.clone(),
+            steps:                          0,
+            t_start:                        None,
+            t_last:                         0f32,
+            position_square_sum:            0f32,
+            position_max:                   0f32,
+            velocity_square_sum:            0f32,
+            velocity_max:                   0f32,
+            last_outside_band:              None,
+            disturbed:                      false,
+            disturbance_start:              None,
+            disturbance_events:             0,
+            convergence_max:                0f32,
+            control_effort:                 0f32,
+            saturation_time:                0f32,
+            estimation_position_square_sum: 0f32,
+            estimation_velocity_square_sum: 0f32,
+            estimation_attitude_square_sum: 0f32,
+            touching:                       false,
+            collisions:                     0,
+            min_clearance:                  f32::INFINITY,
+        }
+    }
+
+    pub fn add (&mut self, record: &Record) {
+        let dt = match self.t_start {
+            Some(_) => record.t - self.t_last,
+            None => {
+                self.t_start = Some(record.t);
+                0f32
+            },
+        };
+        self.t_last = record.t;
+        self.steps += 1;
+
+        let position_error = Vector::magnitude(record.position - record.reference_position);
+        let velocity_error = Vector::magnitude(record.velocity - record.reference_velocity);
+
+        self.position_square_sum += position_error * position_error;
+        self.velocity_square_sum += velocity_error * velocity_error;
+        self.position_max = self.position_max.max(position_error);
+        self.velocity_max = self.velocity_max.max(velocity_error);
+
+        let within_band = position_error <= self.criteria.band;
+
+        if !within_band {
+            self.last_outside_band = Some(record.t);
+        }
+
+        let disturbed = Vector::magnitude(record.disturbance_force)  > self.criteria.force_threshold ||
+                        Vector::magnitude(record.disturbance_torque) > self.criteria.torque_threshold;
+
+        if disturbed && !self.disturbed {
+            self.disturbance_events += 1;
+
+            if self.disturbance_start.is_none() {
+                self.disturbance_start = Some(record.t);
+            }
+        }
+        self.disturbed = disturbed;
+
+        if within_band && !disturbed { //recovered once the disturbance is over and the drone is back within the band
+            if let Some(t_disturbance) = self.disturbance_start.take() {
+                self.convergence_max = self.convergence_max.max(record.t - t_disturbance);
+            }
+        }
+
+        for pwm in &record.pwm {
+            self.control_effort += pwm * dt;
+        }
+
+        if record.saturated {
+            self.saturation_time += dt;
+        }
+
+        let (position_estimation, velocity_estimation, attitude_estimation) = record.estimation_errors();
+
+        self.estimation_position_square_sum += position_estimation * position_estimation;
+        self.estimation_velocity_square_sum += velocity_estimation * velocity_estimation;
+        self.estimation_attitude_square_sum += attitude_estimation * attitude_estimation;
+
+        //starting on the ground isn't a collision
+        if record.collision && !self.touching && self.steps > 1 {
+            self.collisions += 1;
+        }
+        self.touching = record.collision;
+
+        self.min_clearance = self.min_clearance.min(record.clearance);
+    }
+
+    pub fn finish (&self) -> Metrics {
+        let t_start = self.t_start.unwrap_or(0f32);
+        let steps = (self.steps as f32).max(1f32);
+
+        let mut convergence_time_max = self.convergence_max;
+        if let Some(t_disturbance) = self.disturbance_start { //never recovered
+            convergence_time_max = convergence_time_max.max(self.t_last - t_disturbance);
+        }
+
+        Metrics {
+            duration:                self.t_last - t_start,
+            position_rms:            (self.position_square_sum / steps).sqrt(),
+            position_max:            self.position_max,
+            velocity_rms:            (self.velocity_square_sum / steps).sqrt(),
+            velocity_max:            self.velocity_max,
+            settling_time:           self.last_outside_band.map(|t| t - t_start).unwrap_or(0f32),
+            disturbance_events:      self.disturbance_events,
+            convergence_time_max,
+            control_effort:          self.control_effort,
+            saturation_time:         self.saturation_time,
+            estimation_position_rms: (self.estimation_position_square_sum / steps).sqrt(),
+            estimation_velocity_rms: (self.estimation_velocity_square_sum / steps).sqrt(),
+            estimation_attitude_rms: (self.estimation_attitude_square_sum / steps).sqrt(),
+            collisions:              self.collisions,
+            min_clearance:           self.min_clearance,
+        }
+    }
+}
+
+pub fn evaluate (records: &[Record], criteria: &Criteria) -> Metrics {
+    let mut evaluator = Evaluator::new(criteria);
+
+    for record in records {
+        evaluator.add(record);
+    }
+
+    evaluator.finish()
+}
@@ -23,6 +23,24 @@ pub struct Normal {
 
 implement_vertex!(Normal, normal);
 
+//keys the binaries react to, so they don't need to know about glutin events
+#[derive(Clone, Copy, PartialEq)]
+pub enum Key {
+    Space,
+    Left,
+    Right,
+    Up,
+    Down,
+    Comma,
+    Period,
+    Home,
+}
+
+pub struct Input {
+    pub closed: bool,
+    pub keys:   Vec<Key>, //keys pressed since the last poll
+}
+
 struct GraphicsModel {
     vertices: glium::VertexBuffer<Vertex>,
     indices:  glium::IndexBuffer<u16>,
@@ -69,6 +87,45 @@ impl GraphicsManager {
         return false;
     }
 
+    pub fn poll_input (&self) -> Input {
+        use glium::glutin::Event;
+        use glium::glutin::ElementState;
+        use glium::glutin::VirtualKeyCode;
+
+        let mut input = Input {
+            closed: false,
+            keys:   Vec::<Key>::new(),
+        };
+
+        for ev in self.display.poll_events() {
+            match ev {
+                Event::Closed => {
+                    input.closed = true;
+                },
+                Event::KeyboardInput(ElementState::Pressed, _, Some(key_code)) => {
+                    let key = match key_code {
+                        VirtualKeyCode::Space  => Some(Key::Space),
+                        VirtualKeyCode::Left   => Some(Key::Left),
+                        VirtualKeyCode::Right  => Some(Key::Right),
+                        VirtualKeyCode::Up     => Some(Key::Up),
+                        VirtualKeyCode::Down   => Some(Key::Down),
+                        VirtualKeyCode::Comma  => Some(Key::Comma),
+                        VirtualKeyCode::Period => Some(Key::Period),
+                        VirtualKeyCode::Home   => Some(Key::Home),
+                        _ => None,
+                    };
+
+                    if let Some(key) = key {
+                        input.keys.push(key);
+                    }
+                },
+                _ => ()
+            }
+        }
+
+        input
+    }
+
     pub fn setup (&mut self) {
         assert!(self.target.is_none());
 
@@ -96,10 +153,15 @@ impl GraphicsManager {
     pub fn draw_object (&mut self, object :&Object) {
         assert!(self.target.is_some());
 
+        let graphicsmodel_id = match object.model {
+            Some(ref name) => self.load_model(name.as_str()),
+            None => return, //object has no graphics model
+        };
+
         let mut target = self.target.take().unwrap(); //take target, will be placed back later
         
         {   //make shure self becomes accesible before placing back target
-            let gm = self.get_model(object.graphicsmodel_id);
+            let gm = self.get_model(graphicsmodel_id);
             
             let uniforms = uniform! {
                 object_to_camera: (Matrix::translation(object.position) * Matrix::scaling(object.scale) * object.rotation.to_matrix()).data(),
@@ -0,0 +1,309 @@
+use object::Object;
+use vector::Vector;
+use matrix::Matrix;
+use quaternion::Quaternion;
+
+//share of the acceleration by which a step may miss the extrapolation of the last two before the force counts as changed
+const FORCE_JUMP :f32 = 0.1f32;
+
+//what an integrator keeps of an object between steps, only Verlet uses it
+#[derive(Clone, Copy)]
+pub struct IntegratorState {
+    previous_acceleration: Option<Vector>,
+    acceleration_change:   Option<Vector>, //over the last step, None right after the force changed
+}
+
+#[allow(dead_code)]
+impl IntegratorState {
+    pub fn new () -> IntegratorState {
+        IntegratorState {
+            previous_acceleration: None,
+            acceleration_change:   None,
+        }
+    }
+
+    pub fn reset (&mut self) {
+        *self = IntegratorState::new();
+    }
+}
+
+impl Default for IntegratorState {
+    fn default () -> IntegratorState {
+        IntegratorState::new()
+    }
+}
+
+//how Object::update advances one step, force and torque (world space) are held constant during the step
+#[derive(Clone, Copy, PartialEq)]
+pub enum Integrator {
+    SemiImplicitEuler, //first order, velocity first then position, the cheapest
+    Rk4,               //classic fourth order runge kutta, the quaternion is integrated as four numbers and normalized afterwards
+    Verlet,            //second order, velocity verlet for translation, half steps of the angular velocity around the rotation update
+    LieGroup,          //fourth order runge kutta munthe-kaas, the rotation only ever changes through the exponential map
+}
+
+#[allow(dead_code)]
+impl Integrator {
+    pub fn all () -> [Integrator; 4] {
+        [Integrator::SemiImplicitEuler, Integrator::Rk4, Integrator::Verlet, Integrator::LieGroup]
+    }
+
+    pub fn name (&self) -> &'static str {
+        match *self {
+            Integrator::SemiImplicitEuler => "semi_implicit_euler",
+            Integrator::Rk4               => "rk4",
+            Integrator::Verlet            => "verlet",
+            Integrator::LieGroup          => "lie_group",
+        }
+    }
+
+    pub fn from_name (name: &str) -> Option<Integrator> {
+        Integrator::all().iter().find(|integrator| integrator.name() == name).cloned()
+    }
+
+    pub fn step (&self, object: &mut Object, force: Vector, torque: Vector, dt: f32) {
+        let acceleration = force / object.mass;
+
+        //spatial, rk4 and lie_group are exact for a constant force, but forces are only evaluated once per step
+        match *self {
+            Integrator::SemiImplicitEuler => {
+                object.velocity += acceleration * dt;
+                object.position += object.velocity * dt;
+            },
+            Integrator::Verlet => {
+                //velocity verlet, the last step only used the old acceleration for the velocity, finish it with the average.
+                //averaging across a jump of the force (an impulse starting or ending) would take half of it back, so then
+                //the last step stands as it was and the verlet history starts over
+                let state = &mut object.integrator_state;
+
+                if let Some(previous) = state.previous_acceleration {
+                    let expected = previous + state.acceleration_change.unwrap_or_else(Vector::null);
+                    let size = Vector::magnitude(previous).max(Vector::magnitude(acceleration));
+
+                    if Vector::magnitude(acceleration - expected) <= size * FORCE_JUMP {
+                        object.velocity += (acceleration - previous) * (0.5f32 * dt);
+                        state.acceleration_change = Some(acceleration - previous);
+                    } else {
+                        state.acceleration_change = None;
+                    }
+                }
+
+                object.position += object.velocity * dt + acceleration * (0.5f32 * dt * dt);
+                object.velocity += acceleration * dt;
+                state.previous_acceleration = Some(acceleration);
+            },
+            Integrator::Rk4 | Integrator::LieGroup => {
+                object.position += object.velocity * dt + acceleration * (0.5f32 * dt * dt);
+                object.velocity += acceleration * dt;
+            },
+        }
+
+        self.rotate(object, torque, dt);
+    }
+
+    //like step, for callers that can evaluate the force anywhere, force(position, velocity) is called once per stage.
+    //with a constant force it moves exactly like step
+    pub fn step_in_field<F> (&self, object: &mut Object, force: F, torque: Vector, dt: f32) where F: Fn(Vector, Vector) -> Vector {
+        let mass = object.mass;
+        let acceleration = |position: Vector, velocity: Vector| force(position, velocity) / mass;
+
+        let x0 = object.position;
+        let v0 = object.velocity;
+
+        match *self {
+            Integrator::SemiImplicitEuler => {
+                object.velocity = v0 + acceleration(x0, v0) * dt;
+                object.position = x0 + object.velocity * dt;
+            },
+            Integrator::Verlet => {
+                let a0 = acceleration(x0, v0);
+                object.position = x0 + v0 * dt + a0 * (0.5f32 * dt * dt);
+                object.velocity = v0 + (a0 + acceleration(object.position, v0 + a0 * dt)) * (0.5f32 * dt);
+            },
+            Integrator::Rk4 | Integrator::LieGroup => {
+                let a1 = acceleration(x0, v0);
+                let v1 = v0;
+                let a2 = acceleration(x0 + v1 * (dt * 0.5f32), v0 + a1 * (dt * 0.5f32));
+                let v2 = v0 + a1 * (dt * 0.5f32);
+                let a3 = acceleration(x0 + v2 * (dt * 0.5f32), v0 + a2 * (dt * 0.5f32));
+                let v3 = v0 + a2 * (dt * 0.5f32);
+                let a4 = acceleration(x0 + v3 * dt, v0 + a3 * dt);
+                let v4 = v0 + a3 * dt;
+
+                object.position = x0 + (v1 + v2 * 2f32 + v3 * 2f32 + v4) * (dt / 6f32);
+                object.velocity = v0 + (a1 + a2 * 2f32 + a3 * 2f32 + a4) * (dt / 6f32);
+            },
+        }
+
+        //nothing is left for the next step to finish
+        object.integrator_state.reset();
+
+        self.rotate(object, torque, dt);
+    }
+
+    fn rotate (&self, object: &mut Object, torque: Vector, dt: f32) {
+        let body = Body {
+            inertia:         object.inertia,
+            inertia_inverse: object.inertia.inverse().expect("inertia tensor is singular"),
+            torque,
+        };
+
+        let (rotation, angular_velocity) = match *self {
+            Integrator::SemiImplicitEuler => body.semi_implicit_euler(object.rotation, object.angular_velocity, dt),
+            Integrator::Rk4               => body.rk4(object.rotation, object.angular_velocity, dt),
+            Integrator::Verlet            => body.verlet(object.rotation, object.angular_velocity, dt),
+            Integrator::LieGroup          => body.lie_group(object.rotation, object.angular_velocity, dt),
+        };
+
+        object.rotation = rotation;
+        object.rotation.normalize();
+        object.angular_velocity = angular_velocity;
+    }
+}
+
+//everything the rotational equations need besides the state, angular velocities are in world space
+struct Body {
+    inertia:         Matrix, //body frame
+    inertia_inverse: Matrix,
+    torque:          Vector, //world space
+}
+
+impl Body {
+    //euler's equations in the body frame: I * dw/dt = torque - w x (I * w), returned in world space
+    fn angular_acceleration (&self, mut rotation: Quaternion, angular_velocity: Vector) -> Vector {
+        rotation.normalize(); //runge kutta stages drift off the unit sphere
+
+        let local_to_world = rotation.to_matrix();
+        let world_to_local = local_to_world.transpose();
+
+        let torque_local = world_to_local * self.torque;
+        let angular_velocity_local = world_to_local * angular_velocity;
+        let angular_momentum_local = self.inertia * angular_velocity_local;
+
+        local_to_world * (self.inertia_inverse * (torque_local - Vector::cross(angular_velocity_local, angular_momentum_local)))
+    }
+
+    fn semi_implicit_euler (&self, rotation: Quaternion, angular_velocity: Vector, dt: f32) -> (Quaternion, Vector) {
+        let angular_velocity = angular_velocity + self.angular_acceleration(rotation, angular_velocity) * dt;
+
+        (Quaternion::from_vector(angular_velocity * dt) * rotation, angular_velocity)
+    }
+
+    fn rk4 (&self, rotation: Quaternion, angular_velocity: Vector, dt: f32) -> (Quaternion, Vector) {
+        //dq/dt = 0.5 * (0, w) * q
+        let derivative = |q: [f32; 4], w: Vector| {
+            let q = Quaternion::new(q[0], q[1], q[2], q[3]);
+            let dq = (Quaternion::new(0f32, w.x, w.y, w.z) * q).components();
+
+            ([dq[0] * 0.5f32, dq[1] * 0.5f32, dq[2] * 0.5f32, dq[3] * 0.5f32], self.angular_acceleration(q, w))
+        };
+
+        let add = |q: [f32; 4], dq: [f32; 4], h: f32| [q[0] + dq[0] * h, q[1] + dq[1] * h, q[2] + dq[2] * h, q[3] + dq[3] * h];
+
+        let q0 = rotation.components();
+        let w0 = angular_velocity;
+
+        let (dq1, dw1) = derivative(q0, w0);
+        let (dq2, dw2) = derivative(add(q0, dq1, dt * 0.5f32), w0 + dw1 * (dt * 0.5f32));
+        let (dq3, dw3) = derivative(add(q0, dq2, dt * 0.5f32), w0 + dw2 * (dt * 0.5f32));
+        let (dq4, dw4) = derivative(add(q0, dq3, dt), w0 + dw3 * dt);
+
+        let mut q = q0;
+        for n in 0 .. 4 {
+            q[n] += (dq1[n] + dq2[n] * 2f32 + dq3[n] * 2f32 + dq4[n]) * (dt / 6f32);
+        }
+
+        (
+            Quaternion::new(q[0], q[1], q[2], q[3]),
+            w0 + (dw1 + dw2 * 2f32 + dw3 * 2f32 + dw4) * (dt / 6f32),
+        )
+    }
+
+    fn verlet (&self, rotation: Quaternion, angular_velocity: Vector, dt: f32) -> (Quaternion, Vector) {
+        let half = angular_velocity + self.angular_acceleration(rotation, angular_velocity) * (dt * 0.5f32);
+        let rotation = Quaternion::from_vector(half * dt) * rotation;
+
+        (rotation, half + self.angular_acceleration(rotation, half) * (dt * 0.5f32))
+    }
+
+    //q(t) = exp(u(t)) * q0, with du/dt = dexp^-1_u(w) integrated by rk4
+    fn lie_group (&self, rotation: Quaternion, angular_velocity: Vector, dt: f32) -> (Quaternion, Vector) {
+        //dexp^-1 up to the terms a fourth order method needs
+        let dexp_inverse = |u: Vector, w: Vector| {
+            let uw = Vector::cross(u, w);
+            w - uw * 0.5f32 + Vector::cross(u, uw) * (1f32 / 12f32)
+        };
+
+        let stage = |u: Vector, w: Vector| {
+            let q = Quaternion::from_vector(u) * rotation;
+            (dexp_inverse(u, w) * dt, self.angular_acceleration(q, w) * dt)
+        };
+
+        let w0 = angular_velocity;
+
+        let (k1, l1) = stage(Vector::null(), w0);
+        let (k2, l2) = stage(k1 * 0.5f32, w0 + l1 * 0.5f32);
+        let (k3, l3) = stage(k2 * 0.5f32, w0 + l2 * 0.5f32);
+        let (k4, l4) = stage(k3, w0 + l3);
+
+        let u = (k1 + k2 * 2f32 + k3 * 2f32 + k4) / 6f32;
+
+        (
+            Quaternion::from_vector(u) * rotation,
+            w0 + (l1 + l2 * 2f32 + l3 * 2f32 + l4) / 6f32,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn one_step_impulses_are_kept () {
+        for integrator in Integrator::all().iter() {
+            let mut object = Object::new();
+            object.update(Vector::new(2f32, 0f32, 0f32, 0f32), Vector::null(), 0.01f32, *integrator);
+
+            for _ in 0 .. 10 {
+                object.update(Vector::null(), Vector::null(), 0.01f32, *integrator);
+            }
+
+            assert!((object.velocity.x - 0.02f32).abs() < 1e-6f32, "{} keeps {} of the impulse", integrator.name(), object.velocity.x / 0.02f32);
+        }
+    }
+
+    #[test]
+    fn verlet_keeps_impulses_under_a_steady_force () {
+        let mut object = Object::new();
+        let gravity = Vector::new(0f32, 0f32, -1f32, 0f32);
+
+        for n in 0 .. 20 {
+            let impulse = if n == 10 { Vector::new(2f32, 0f32, 0f32, 0f32) } else { Vector::null() };
+            object.update(gravity + impulse, Vector::null(), 0.01f32, Integrator::Verlet);
+        }
+
+        assert!((object.velocity.x - 0.02f32).abs() < 1e-6f32, "keeps {} of the impulse", object.velocity.x / 0.02f32);
+        assert!((object.velocity.z + 0.2f32).abs() < 1e-6f32);
+    }
+
+    #[test]
+    fn constant_fields_move_like_step () {
+        for integrator in Integrator::all().iter() {
+            let force = Vector::new(1f32, -2f32, 3f32, 0f32);
+
+            let mut stepped = Object::new();
+            let mut fielded = Object::new();
+            stepped.velocity = Vector::new(0.5f32, 0f32, 0f32, 0f32);
+            fielded.velocity = stepped.velocity;
+
+            for _ in 0 .. 10 {
+                stepped.update(force, Vector::null(), 0.01f32, *integrator);
+                integrator.step_in_field(&mut fielded, |_, _| force, Vector::null(), 0.01f32);
+            }
+
+            assert!(Vector::magnitude(stepped.position - fielded.position) < 1e-6f32, "{}", integrator.name());
+            assert!(Vector::magnitude(stepped.velocity - fielded.velocity) < 1e-6f32, "{}", integrator.name());
+        }
+    }
+}
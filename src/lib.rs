@@ -0,0 +1,35 @@
+
+#[cfg(feature = "graphics")]
+#[macro_use]
+extern crate glium;
+#[cfg(feature = "graphics")]
+extern crate image;
+extern crate rand;
+
+pub mod utils;
+pub mod random;
+pub mod vector;
+pub mod matrix;
+pub mod quaternion;
+pub mod spline;
+
+pub mod integrator;
+pub mod object;
+pub mod collision;
+pub mod objectmanager;
+pub mod motor;
+pub mod drone;
+pub mod pid;
+pub mod controller;
+pub mod scheduler;
+pub mod sensor;
+pub mod estimator;
+pub mod aerodynamics;
+pub mod disturbance;
+pub mod scenario;
+pub mod recorder;
+pub mod evaluation;
+pub mod simulation;
+
+#[cfg(feature = "graphics")]
+pub mod graphicsmanager;
@@ -1,160 +1,103 @@
-#[macro_use]
-extern crate glium;
-extern crate image;
-extern crate rand;
-
-mod object;
-mod graphicsmanager;
-mod utils;
-
-mod drone;
-mod spline;
-use spline::Spline;
-use spline::Bezier;
-mod vector;
-use vector::Vector;
-mod matrix;
-//use matrix::Matrix;
-mod quaternion;
-use quaternion::Quaternion;
-mod objectmanager;
-
-
-const MERGE_TIME :f32 = 1f32;
-const DT :f32 = 0.02f32;  //timestep size
-const DISTURB :bool = true;    
-
-pub fn make_object (gm :&mut graphicsmanager::GraphicsManager, name: &str) -> object::Object {
-    object::Object {
-        scale: 1f32,
-        mass: 1f32,
-        angular_inertia: 600.0f32,
-        position: Vector::origin(),
-        velocity: Vector::null(),
-        rotation: Quaternion::identity(),
-        angular_velocity: Vector::null(),
-        graphicsmodel_id:  gm.load_model(name),
-    }
-}
+extern crate dronesim;
 
-fn main () {
-    let mut gm = graphicsmanager::GraphicsManager::new();
-    let mut object_manager = objectmanager::ObjectManager::new();
-
-    let target       :objectmanager::ObjectTag;
-    let merge_target :objectmanager::ObjectTag;
-    let drone        :objectmanager::ObjectTag;
-    
-    { //target
-        let mut new_cube = make_object(&mut gm, "cube");
-        new_cube.scale = 0.25f32;
-
-        target = object_manager.push_object_tagged(new_cube);
-    }
-    { //merge_target
-        let mut new_cube = make_object(&mut gm, "cube");
-        new_cube.scale = 0.25f32;
+use dronesim::graphicsmanager::GraphicsManager;
+use dronesim::graphicsmanager::Key;
+use dronesim::simulation::Simulation;
+use dronesim::scenario::Scenario;
+use dronesim::scheduler::Task;
 
-        merge_target = object_manager.push_object_tagged(new_cube);
-    }
+use std::env;
+use std::time::Instant;
 
-    { //drone
-        let mut new_drone = make_object(&mut gm, "drone");
-        new_drone.scale = 0.6f32;
-        new_drone.mass = 1f32;
-        new_drone.angular_inertia = 1f32;
-        new_drone.position = Vector::new(0f32, 0f32, -6f32, 1f32);
+const MAX_FRAME_TIME :f32 = 0.25f32; //wall clock seconds a slow frame may make up for, the rest is dropped instead of stalling
 
-        drone = object_manager.push_object_tagged(new_drone);
-    }
+/* controls;
+space         pause / resume
+up / down     double / halve the time scale, 1 is real time, above is faster, below is slow motion
+home          back to real time
 
-    let spline = spline::test_spline();
+usage: glium_tutorial_1 [SCENARIO] [--speed TIME_SCALE]
+*/
 
-    { //put drone in initial state
-        let (p, v, _) = spline.sample_all(0f32);
-        let obj = object_manager.get_mut_object(&drone);
-        obj.position = p;
-        obj.velocity = v;
+fn main () {
+    let mut scenario_file = "scenarios/test_flight.txt".to_string();
+    let mut time_scale = 1f32;
+
+    let mut args = env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--speed" => {
+                time_scale = args.next().expect("--speed requires a value").parse().expect("--speed requires a number");
+            },
+            _ => {
+                scenario_file = arg;
+            },
+        }
     }
 
-    let mut t :f32 = 0f32;  //current time  
+    let scenario = Scenario::load(scenario_file.as_str());
+    let mut sim = Simulation::new(&scenario);
+    let mut gm = GraphicsManager::new();
 
+    println!("seed: {}", sim.rng.seed());
+    println!("physics at {} Hz", 1f32 / sim.dt);
+    for task in Task::all().iter() {
+        println!("{} at {} Hz", task.name(), 1f32 / sim.scheduler.period(*task));
+    }
     println!("running!");
-    
+
+    let mut paused      = false;
+    let mut accumulator = 0f32; //simulated time owed to the physics
+    let mut previous    = sim.object_manager.poses(); //state before the last physics step
+    let mut last_frame  = Instant::now();
+
     loop {
-        if gm.exit() {
+        let input = gm.poll_input();
+
+        if input.closed {
             break;
         }
 
-        
-        t += DT;
-        println!("t: {}", t);
-        let t2 = t % spline.duration();
-        let t3 = (t + MERGE_TIME) % spline.duration();
-
-        
-        {   //update target
-            let (p, _, _) = spline.sample_all(t2);
-            let obj = object_manager.get_mut_object(&target);
-            obj.position = p;
+        for key in input.keys {
+            match key {
+                Key::Space => paused = !paused,
+                Key::Up    => time_scale *= 2f32,
+                Key::Down  => time_scale *= 0.5f32,
+                Key::Home  => time_scale = 1f32,
+                _ => continue,
+            }
+
+            println!("t: {} time scale: {}x {}", sim.t, time_scale, if paused { "paused" } else { "running" });
         }
 
-        {   //update merge_target
-            let (p, _, _) = spline.sample_all(t3);
-            let obj = object_manager.get_mut_object(&merge_target);
-            obj.position = p;
+        let now = Instant::now();
+        let elapsed = now.duration_since(last_frame);
+        let frame_dt = elapsed.as_secs() as f32 + elapsed.subsec_nanos() as f32 * 1e-9f32;
+        last_frame = now;
+
+        if !paused {
+            accumulator += frame_dt.min(MAX_FRAME_TIME) * time_scale;
         }
 
-        
-        //let gravity = Vector::null();
-        let gravity = Vector::ey() * -10f32;
-        
-        let (mut pwm1, mut pwm2, mut pwm3, mut pwm4) = {
-            let obj = object_manager.get_object(&drone);
-
-            let (p_merge, v_merge, _) = spline.sample_all(t3);
-
-            let mut s2 = Spline::new();
-            s2.push_curve(
-                Bezier::merge(
-                    obj.position,
-                    obj.velocity,
-                    t,
-                    p_merge,
-                    v_merge,
-                    t + MERGE_TIME
-                )
-            );
-
-            let a = s2.sample_acceleration(t);
-
-            drone::get_pwm(obj, a - gravity)
-        };
-
-        pwm1 = pwm1.max(0f32);
-        pwm2 = pwm2.max(0f32);
-        pwm3 = pwm3.max(0f32);
-        pwm4 = pwm4.max(0f32);
-        
-        drone::apply_pwm(&mut object_manager, &drone, pwm1, pwm2, pwm3, pwm4);
-        object_manager.apply_force(gravity, &drone);
-        
-        if DISTURB {
-            object_manager.apply_force(Vector::random_unitvector() * 2f32, &drone);
-            object_manager.apply_torque(Vector::random_unitvector() * 5f32, &drone);
-
-            if t % 10f32 < 1f32 {
-                object_manager.apply_force(Vector::ez() * -3f32, &drone);
+        //fixed steps, however long the frame took
+        while accumulator >= sim.dt {
+            previous = sim.object_manager.poses();
+            sim.step();
+            accumulator -= sim.dt;
+
+            for collision in &sim.collisions {
+                let other = match collision.b {
+                    Some(ref tag) => sim.name(tag),
+                    None => "the ground",
+                };
+                println!("t {}: {} hit {}", sim.t, sim.name(&collision.a), other);
             }
         }
 
-            
-        object_manager.update_physics(DT);
-        
-        //rendering
+        //rendering, between the last two physics states
         gm.setup();
-        object_manager.draw(&mut gm);
+        sim.object_manager.draw_interpolated(&mut gm, &previous, accumulator / sim.dt);
         gm.finish_frame();
     }
 }
-
@@ -25,6 +25,16 @@ impl Matrix {
         ])
     }
     
+    //symmetric 3x3 part from diagonal (xx, yy, zz) and off diagonal (xy, xz, yz) entries, e.g. an inertia tensor
+    pub fn symmetric (xx :f32, yy :f32, zz :f32, xy :f32, xz :f32, yz :f32) -> Matrix {
+        Matrix([
+            Vector::new(xx,  xy,  xz,  0.0),
+            Vector::new(xy,  yy,  yz,  0.0),
+            Vector::new(xz,  yz,  zz,  0.0),
+            Vector::new(0.0, 0.0, 0.0, 1.0),
+        ])
+    }
+
     pub fn translation (mut v :Vector) -> Matrix {
         assert!(v.w == 0.0f32 || v.w == 1.0f32);
 
@@ -64,6 +74,69 @@ impl Matrix {
             [self.0[3].x, self.0[3].y, self.0[3].z, self.0[3].w]
         ]
     }
+
+    //inverse of data()
+    pub fn from_data (data: [[f32; 4]; 4]) -> Matrix {
+        Matrix([
+            Vector::new(data[0][0], data[0][1], data[0][2], data[0][3]),
+            Vector::new(data[1][0], data[1][1], data[1][2], data[1][3]),
+            Vector::new(data[2][0], data[2][1], data[2][2], data[2][3]),
+            Vector::new(data[3][0], data[3][1], data[3][2], data[3][3]),
+        ])
+    }
+
+    pub fn transpose (self) -> Matrix {
+        let d = self.data();
+        let mut t = [[0f32; 4]; 4];
+
+        for i in 0 .. 4 {
+            for j in 0 .. 4 {
+                t[i][j] = d[j][i];
+            }
+        }
+
+        Matrix::from_data(t)
+    }
+
+    //gauss-jordan with partial pivoting, None if the matrix is (numerically) singular
+    pub fn inverse (self) -> Option<Matrix> {
+        let mut a = self.transpose().data(); //rows
+        let mut r = Matrix::identity().data();
+
+        for column in 0 .. 4 {
+            let mut pivot = column;
+            for row in column + 1 .. 4 {
+                if a[row][column].abs() > a[pivot][column].abs() {
+                    pivot = row;
+                }
+            }
+
+            if a[pivot][column].abs() < 1e-12f32 {
+                return None;
+            }
+
+            a.swap(column, pivot);
+            r.swap(column, pivot);
+
+            let scale = 1f32 / a[column][column];
+            for j in 0 .. 4 {
+                a[column][j] *= scale;
+                r[column][j] *= scale;
+            }
+
+            for row in 0 .. 4 {
+                if row != column {
+                    let factor = a[row][column];
+                    for j in 0 .. 4 {
+                        a[row][j] -= factor * a[column][j];
+                        r[row][j] -= factor * r[column][j];
+                    }
+                }
+            }
+        }
+
+        Some(Matrix::from_data(r).transpose())
+    }
 }
 
 impl Mul<Vector> for Matrix {
@@ -0,0 +1,99 @@
+//static and dynamic properties of one rotor, pwm (0 .. 1) -> rpm -> thrust and drag torque
+#[derive(Clone, Copy)]
+pub struct MotorModel {
+    pub spin_up_time:       f32, //first order time constant when speeding up, 0 -> instant
+    pub spin_down_time:     f32, //same when slowing down
+    pub max_rpm:            f32, //rpm at pwm = 1, rpm is linear in pwm
+    pub idle_pwm:           f32, //armed motors never spin slower than this
+    pub thrust_coefficient: f32, //thrust = thrust_coefficient * rpm^2
+    pub drag_coefficient:   f32, //drag torque = drag_coefficient * rpm^2, around the rotor axis, against the spin
+}
+
+#[allow(dead_code)]
+impl MotorModel {
+    //instant, and with a unit ratio between drag torque and thrust, like the original linear pwm model,
+    //unlike it every rotor is limited to 0 .. 10 N, the original took any thrust, negative ones too
+    pub fn new () -> MotorModel {
+        MotorModel {
+            spin_up_time:       0f32,
+            spin_down_time:     0f32,
+            max_rpm:            10000f32,
+            idle_pwm:           0f32,
+            thrust_coefficient: 1e-7f32,
+            drag_coefficient:   1e-7f32,
+        }
+    }
+
+    pub fn min_thrust (&self) -> f32 {
+        self.thrust_at(self.idle_pwm * self.max_rpm)
+    }
+
+    pub fn max_thrust (&self) -> f32 {
+        self.thrust_at(self.max_rpm)
+    }
+
+    pub fn thrust_at (&self, rpm: f32) -> f32 {
+        self.thrust_coefficient * rpm * rpm
+    }
+
+    pub fn drag_torque_at (&self, rpm: f32) -> f32 {
+        self.drag_coefficient * rpm * rpm
+    }
+
+    //steady state rpm for a thrust, negative thrusts give negative rpm so saturation stays visible
+    pub fn rpm_for_thrust (&self, thrust: f32) -> f32 {
+        (thrust.abs() / self.thrust_coefficient).sqrt() * thrust.signum()
+    }
+
+    //inverse of the static map, what the flight controller sends for a desired thrust (not clamped)
+    pub fn pwm_for_thrust (&self, thrust: f32) -> f32 {
+        self.rpm_for_thrust(thrust) / self.max_rpm
+    }
+}
+
+impl Default for MotorModel {
+    fn default () -> MotorModel {
+        MotorModel::new()
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Motor {
+    pub model: MotorModel,
+    pub rpm:   f32,
+}
+
+#[allow(dead_code)]
+impl Motor {
+    pub fn new (model: MotorModel) -> Motor {
+        Motor {
+            model,
+            rpm:   0f32,
+        }
+    }
+
+    //pwm gets clamped to idle_pwm .. 1, the rotor then approaches its target rpm with the spin up/down time constant
+    pub fn update (&mut self, pwm: f32, dt: f32) {
+        let rpm_target = pwm.max(self.model.idle_pwm).min(1f32) * self.model.max_rpm;
+
+        let time_constant = if rpm_target > self.rpm {
+            self.model.spin_up_time
+        } else {
+            self.model.spin_down_time
+        };
+
+        if time_constant <= 0f32 {
+            self.rpm = rpm_target;
+        } else {
+            self.rpm += (rpm_target - self.rpm) * (1f32 - (-dt / time_constant).exp());
+        }
+    }
+
+    pub fn thrust (&self) -> f32 {
+        self.model.thrust_at(self.rpm)
+    }
+
+    pub fn drag_torque (&self) -> f32 {
+        self.model.drag_torque_at(self.rpm)
+    }
+}
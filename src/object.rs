@@ -1,32 +1,55 @@
 use vector::Vector;
 use quaternion::Quaternion;
+use matrix::Matrix;
+use integrator::Integrator;
+use integrator::IntegratorState;
 
+#[derive(Clone)]
 pub struct Object {
     pub scale:            f32,
     pub mass:             f32, //if mass == Infinity -> object is fixed (but might still rotate!)
-    pub angular_inertia:  f32,
+    pub inertia:          Matrix, //body frame inertia tensor, only the 3x3 part is used
     pub position:         Vector,
     pub velocity:         Vector,
     pub rotation:         Quaternion,
     pub angular_velocity: Vector,
+    pub integrator_state: IntegratorState, //kept by the integrator between steps
     
-    pub graphicsmodel_id: usize,
+    pub model:            Option<String>, //name of the graphics model, None -> object is not drawn
 }
 
 #[allow(dead_code)]
 impl Object {
-    pub fn update (&mut self, force: Vector, torque: Vector, dt: f32) {
+    pub fn new () -> Object {
+        Object {
+            scale: 1f32,
+            mass: 1f32,
+            inertia: Matrix::scaling(600.0f32),
+            position: Vector::origin(),
+            velocity: Vector::null(),
+            rotation: Quaternion::identity(),
+            angular_velocity: Vector::null(),
+            integrator_state: IntegratorState::new(),
+            model: None,
+        }
+    }
+
+    pub fn with_model (name: &str) -> Object {
+        let mut object = Object::new();
+        object.model = Some(name.to_string());
+        object
+    }
+
+    pub fn update (&mut self, force: Vector, torque: Vector, dt: f32, integrator: Integrator) {
         assert!(force.w == 0f32);
         assert!(torque.w == 0f32);
 
-        //spatial
-        let acceleration = force / self.mass;
-        self.velocity += acceleration * dt;
-        self.position += self.velocity * dt;
+        integrator.step(self, force, torque, dt);
+    }
+}
 
-        //rotational
-        self.angular_velocity += torque * (dt / self.angular_inertia);
-        self.rotation = Quaternion::from_vector(self.angular_velocity * dt) * self.rotation;
-        self.rotation.normalize();
+impl Default for Object {
+    fn default () -> Object {
+        Object::new()
     }
 }
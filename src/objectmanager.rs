@@ -1,22 +1,103 @@
+use std::cmp::Ordering;
+
 use vector::Vector;
 use object::Object;
+use quaternion::Quaternion;
+use integrator::Integrator;
+use matrix::Matrix;
+use collision;
+use collision::Shape;
+use collision::Aabb;
+use collision::Contact;
+use collision::Material;
+#[cfg(feature = "graphics")]
 use graphicsmanager::GraphicsManager;
 
+const CONTACT_ITERATIONS :usize = 10; //passes over all contact points per physics step
+const CONTACT_TOLERANCE  :f32 = 0.01f32; //m, corners this close count as contact points, they may close the gap within a step
+const PENETRATION_SLOP   :f32 = 0.005f32; //m, overlap that is left alone, so resting objects keep touching
+const CORRECTION         :f32 = 0.5f32; //share of the remaining overlap pushed apart every step
+const RESTING_SPEED      :f32 = 0.5f32; //m/s, slower approaches don't bounce, so resting objects come to rest
+
+#[derive(Clone, Copy, PartialEq)]
 pub struct ObjectTag (usize);
 
+//how an object answers an impulse, None for fixed objects, only built for objects with contact points
+#[derive(Clone, Copy)]
+struct Response {
+    inverse_mass:    f32,
+    local_to_world:  Matrix,
+    world_to_local:  Matrix,
+    inertia_inverse: Matrix, //body frame
+}
+
+impl Response {
+    fn of (object: &Object) -> Option<Response> {
+        if !object.mass.is_finite() {
+            return None;
+        }
+
+        let local_to_world = object.rotation.to_matrix();
+
+        Some(Response {
+            inverse_mass:    1f32 / object.mass,
+            local_to_world,
+            world_to_local:  local_to_world.transpose(),
+            inertia_inverse: object.inertia.inverse().unwrap_or(Matrix::scaling(0f32)), //singular -> impulses don't turn it
+        })
+    }
+
+    //world frame angular velocity change from a world frame angular impulse
+    fn turn (&self, angular_impulse: Vector) -> Vector {
+        self.local_to_world * (self.inertia_inverse * (self.world_to_local * angular_impulse))
+    }
+
+    //inverse of the mass felt along direction at offset from the center
+    fn inverse_mass (&self, offset: Vector, direction: Vector) -> f32 {
+        self.inverse_mass + Vector::dot(Vector::cross(self.turn(Vector::cross(offset, direction)), offset), direction)
+    }
+}
+
+//one point of a touching pair, b is None for the ground
+struct ContactPoint {
+    a:                usize,
+    b:                Option<usize>,
+    normal:           Vector, //from b to a
+    offset_a:         Vector, //from the centers to the point
+    offset_b:         Vector,
+    target:           f32,    //lowest normal velocity allowed, bounce or closing the gap
+    friction:         f32,
+    normal_impulse:   f32,    //accumulated over the iterations
+    friction_impulse: Vector,
+}
+
 pub struct ObjectManager {
+    pub integrator:     Integrator, //used by update_physics for every object
+    pub ground:         Option<f32>, //height of the ground plane along world y, None -> no ground
+    pub ground_surface: Material,
+    pub response:       bool, //false -> shapes are only detected, they pass through each other
     objects: Vec<Object>,
     forces: Vec<Vector>,
     torques: Vec<Vector>,
+    shapes: Vec<Option<Shape>>, //None -> the object doesn't collide
+    materials: Vec<Material>,
+    impulses: Vec<Vector>, //contact impulse of the last physics update
 }
 
 #[allow(dead_code)]
 impl ObjectManager {
     pub fn new () -> ObjectManager {
         ObjectManager {
-            objects: Vec::<Object>::new(),
-            forces:  Vec::<Vector>::new(),
-            torques: Vec::<Vector>::new(),
+            integrator:     Integrator::SemiImplicitEuler,
+            ground:         None,
+            ground_surface: Material::new(),
+            response:       true,
+            objects:        Vec::<Object>::new(),
+            forces:         Vec::<Vector>::new(),
+            torques:        Vec::<Vector>::new(),
+            shapes:         Vec::<Option<Shape>>::new(),
+            materials:      Vec::<Material>::new(),
+            impulses:       Vec::<Vector>::new(),
         }
     }
 
@@ -27,6 +108,9 @@ impl ObjectManager {
         self.objects.push(new_object);
         self.forces.push(Vector::null());
         self.torques.push(Vector::null());
+        self.shapes.push(None);
+        self.materials.push(Material::new());
+        self.impulses.push(Vector::null());
     }
 
     pub fn push_object_tagged (&mut self, new_object: Object) -> ObjectTag {
@@ -36,6 +120,9 @@ impl ObjectManager {
         self.objects.push(new_object);
         self.forces.push(Vector::null());
         self.torques.push(Vector::null());
+        self.shapes.push(None);
+        self.materials.push(Material::new());
+        self.impulses.push(Vector::null());
 
         ObjectTag(self.objects.len() - 1)
     }
@@ -76,19 +163,324 @@ impl ObjectManager {
         self.torques[tag.0] += torque;
     }
 
+    //force applied to the object since the last physics update
+    pub fn get_force (&self, tag: &ObjectTag) -> Vector {
+        assert!(self.objects.len() == self.forces.len());
+
+        self.forces[tag.0]
+    }
+
+    pub fn set_shape (&mut self, shape: Option<Shape>, tag: &ObjectTag) {
+        assert!(self.objects.len() == self.shapes.len());
+
+        self.shapes[tag.0] = shape;
+    }
+
+    pub fn get_shape (&self, tag: &ObjectTag) -> Option<&Shape> {
+        assert!(self.objects.len() == self.shapes.len());
+
+        self.shapes[tag.0].as_ref()
+    }
+
+    pub fn set_material (&mut self, material: Material, tag: &ObjectTag) {
+        assert!(self.objects.len() == self.materials.len());
+
+        self.materials[tag.0] = material;
+    }
+
+    pub fn get_material (&self, tag: &ObjectTag) -> Material {
+        assert!(self.objects.len() == self.materials.len());
+
+        self.materials[tag.0]
+    }
+
+    //world frame impulse the contacts gave the object during the last physics update, N s
+    pub fn get_contact_impulse (&self, tag: &ObjectTag) -> Vector {
+        assert!(self.objects.len() == self.impulses.len());
+
+        self.impulses[tag.0]
+    }
+
+    //every pair of shapes closer than margin and every shape closer than margin to the ground,
+    //fixed objects don't collide with each other or the ground, broad phase is a sweep along world x
+    pub fn contacts (&self, margin: f32) -> Vec<Contact> {
+        assert!(self.objects.len() == self.shapes.len());
+
+        let mut boxes = Vec::<(usize, Aabb)>::new();
+
+        for n in 0 .. self.objects.len() {
+            if let Some(ref shape) = self.shapes[n] {
+                boxes.push((n, Aabb::of(shape, &self.objects[n])));
+            }
+        }
+
+        boxes.sort_by(|lhs, rhs| lhs.1.min.x.partial_cmp(&rhs.1.min.x).unwrap_or(Ordering::Equal));
+
+        let mut contacts = Vec::<Contact>::new();
+
+        for i in 0 .. boxes.len() {
+            let (n, aabb_n) = boxes[i];
+            let shape_n = self.shapes[n].as_ref().unwrap();
+
+            if let Some(height) = self.ground {
+                if aabb_n.min.y <= height + margin && self.objects[n].mass.is_finite() {
+                    if let Some((normal, depth, point)) = collision::collide_ground(shape_n, &self.objects[n], height, margin) {
+                        contacts.push(Contact {
+                            a:      ObjectTag(n),
+                            b:      None,
+                            normal,
+                            depth,
+                            point,
+                        });
+                    }
+                }
+            }
+
+            for &(m, aabb_m) in &boxes[i + 1 ..] {
+                if aabb_m.min.x > aabb_n.max.x + margin {
+                    break; //sorted, nothing further along x can be close
+                }
+
+                if !aabb_n.overlaps(&aabb_m, margin) {
+                    continue;
+                }
+
+                if !self.objects[n].mass.is_finite() && !self.objects[m].mass.is_finite() {
+                    continue;
+                }
+
+                //lower index first, so a pair always shows up the same way
+                let (a, b) = if n < m { (n, m) } else { (m, n) };
+
+                let touch = collision::collide(
+                    self.shapes[a].as_ref().unwrap(), &self.objects[a],
+                    self.shapes[b].as_ref().unwrap(), &self.objects[b],
+                    margin
+                );
+
+                if let Some((normal, depth, point)) = touch {
+                    contacts.push(Contact {
+                        a:      ObjectTag(a),
+                        b:      Some(ObjectTag(b)),
+                        normal,
+                        depth,
+                        point,
+                    });
+                }
+            }
+        }
+
+        contacts
+    }
+
     pub fn update_physics (&mut self, dt: f32) {
         assert!(self.objects.len() == self.forces.len());
         assert!(self.objects.len() == self.torques.len());
         
         for n in 0 .. self.objects.len() {
             if self.objects[n].mass.is_finite() {
-                self.objects[n].update(self.forces[n], self.torques[n], dt);
+                self.objects[n].update(self.forces[n], self.torques[n], dt, self.integrator);
                 self.forces[n] = Vector::null();
                 self.torques[n] = Vector::null();
             }
         }
+
+        for impulse in self.impulses.iter_mut() {
+            *impulse = Vector::null();
+        }
+
+        if self.response {
+            self.resolve_contacts(dt);
+        }
     }
 
+    //sequential impulses on the velocities of touching shapes with restitution and coulomb friction,
+    //then pushes the overlap apart, fixed objects take any impulse without moving
+    fn resolve_contacts (&mut self, dt: f32) {
+        let contacts = self.contacts(CONTACT_TOLERANCE);
+
+        if contacts.is_empty() {
+            return;
+        }
+
+        let mut responses = vec![None; self.objects.len()];
+        let mut points = Vec::<ContactPoint>::new();
+
+        for contact in &contacts {
+            let a = contact.a.0;
+            let shape_a = self.shapes[a].as_ref().unwrap();
+
+            let (b, material, manifold) = match contact.b {
+                Some(ObjectTag(b)) => (
+                    Some(b),
+                    self.materials[a].combine(&self.materials[b]),
+                    collision::manifold(shape_a, &self.objects[a], self.shapes[b].as_ref().unwrap(), &self.objects[b], contact, CONTACT_TOLERANCE),
+                ),
+                None => (
+                    None,
+                    self.materials[a].combine(&self.ground_surface),
+                    collision::ground_manifold(shape_a, &self.objects[a], self.ground.unwrap(), CONTACT_TOLERANCE),
+                ),
+            };
+
+            if !manifold.is_empty() {
+                for n in Some(a).into_iter().chain(b) {
+                    responses[n] = responses[n].or_else(|| Response::of(&self.objects[n]));
+                }
+            }
+
+            for (depth, point) in manifold {
+                let offset_a = point - self.objects[a].position;
+                let offset_b = match b {
+                    Some(b) => point - self.objects[b].position,
+                    None => Vector::null(),
+                };
+
+                let mut contact_point = ContactPoint {
+                    a,
+                    b,
+                    normal:           contact.normal,
+                    offset_a,
+                    offset_b,
+                    target:           0f32,
+                    friction:         material.friction,
+                    normal_impulse:   0f32,
+                    friction_impulse: Vector::null(),
+                };
+
+                let approach = -Vector::dot(self.relative_velocity(&contact_point), contact.normal);
+
+                contact_point.target = if approach > RESTING_SPEED && approach * dt >= -depth {
+                    approach * material.restitution //touches within the step, bounces right away
+                } else if depth < 0f32 {
+                    depth / dt //may close the gap, but not more
+                } else {
+                    0f32
+                };
+
+                points.push(contact_point);
+            }
+        }
+
+        for _ in 0 .. CONTACT_ITERATIONS {
+            for point in points.iter_mut() {
+                let response_a = responses[point.a];
+                let response_b = point.b.and_then(|b| responses[b]);
+                let (offset_a, offset_b) = (point.offset_a, point.offset_b);
+
+                let inverse_mass = |direction: Vector| {
+                    response_a.map_or(0f32, |response| response.inverse_mass(offset_a, direction)) +
+                    response_b.map_or(0f32, |response| response.inverse_mass(offset_b, direction))
+                };
+
+                //normal, never pulling
+                let normal_velocity = Vector::dot(self.relative_velocity(point), point.normal);
+                let k = inverse_mass(point.normal);
+
+                if k <= 0f32 {
+                    continue;
+                }
+
+                let total = (point.normal_impulse + (point.target - normal_velocity) / k).max(0f32);
+                let change = total - point.normal_impulse;
+                point.normal_impulse = total;
+                self.apply_contact_impulse(point, response_a, response_b, point.normal * change);
+
+                //friction against the sliding direction, within the cone of the normal impulse
+                let velocity = self.relative_velocity(point);
+                let sliding = velocity - point.normal * Vector::dot(velocity, point.normal);
+
+                if let Some(direction) = Vector::normalize(sliding) {
+                    let k = inverse_mass(direction);
+                    let mut total = point.friction_impulse - direction * (Vector::magnitude(sliding) / k);
+                    let limit = point.friction * point.normal_impulse;
+
+                    if Vector::magnitude(total) > limit {
+                        total = Vector::normalize(total).map_or(Vector::null(), |unit| unit * limit);
+                    }
+
+                    let change = total - point.friction_impulse;
+                    point.friction_impulse = total;
+                    self.apply_contact_impulse(point, response_a, response_b, change);
+                }
+            }
+        }
+
+        //positions, once per pair by its deepest overlap, split by the inverse masses
+        for contact in &contacts {
+            let a = contact.a.0;
+            let b = contact.b.map(|tag| tag.0);
+
+            let inverse_a = 1f32 / self.objects[a].mass; //0 for fixed objects
+            let inverse_b = b.map_or(0f32, |b| 1f32 / self.objects[b].mass);
+
+            let overlap = contact.depth - PENETRATION_SLOP;
+
+            if overlap <= 0f32 || inverse_a + inverse_b <= 0f32 {
+                continue;
+            }
+
+            let push = contact.normal * (CORRECTION * overlap / (inverse_a + inverse_b));
+
+            self.objects[a].position += push * inverse_a;
+            if let Some(b) = b {
+                self.objects[b].position -= push * inverse_b;
+            }
+        }
+    }
+
+    //velocity of the point on a relative to the one on b
+    fn relative_velocity (&self, point: &ContactPoint) -> Vector {
+        let velocity_at = |n: usize, offset: Vector| {
+            let object = &self.objects[n];
+            object.velocity + Vector::cross(object.angular_velocity, offset)
+        };
+
+        let velocity_b = match point.b {
+            Some(b) => velocity_at(b, point.offset_b),
+            None => Vector::null(),
+        };
+
+        velocity_at(point.a, point.offset_a) - velocity_b
+    }
+
+    //impulse on a at the point, the opposite one on b
+    fn apply_contact_impulse (&mut self, point: &ContactPoint, response_a: Option<Response>, response_b: Option<Response>, impulse: Vector) {
+        if let Some(response) = response_a {
+            let object = &mut self.objects[point.a];
+            object.velocity += impulse * response.inverse_mass;
+            object.angular_velocity += response.turn(Vector::cross(point.offset_a, impulse));
+            self.impulses[point.a] += impulse;
+        }
+
+        if let (Some(b), Some(response)) = (point.b, response_b) {
+            let object = &mut self.objects[b];
+            object.velocity -= impulse * response.inverse_mass;
+            object.angular_velocity -= response.turn(Vector::cross(point.offset_b, impulse));
+            self.impulses[b] -= impulse;
+        }
+    }
+
+    //position and rotation of every object, to interpolate between physics steps
+    pub fn poses (&self) -> Vec<(Vector, Quaternion)> {
+        self.objects.iter().map(|obj| (obj.position, obj.rotation)).collect()
+    }
+
+    //draws every object alpha of the way from the previous poses to its current state, alpha in 0 .. 1
+    #[cfg(feature = "graphics")]
+    pub fn draw_interpolated (&self, gm: &mut GraphicsManager, previous: &[(Vector, Quaternion)], alpha: f32) {
+        assert!(self.objects.len() == previous.len());
+
+        for (obj, &(position, rotation)) in self.objects.iter().zip(previous.iter()) {
+            let mut interpolated = obj.clone();
+            interpolated.position = position + (obj.position - position) * alpha;
+            interpolated.rotation = Quaternion::slerp(rotation, obj.rotation, alpha);
+
+            gm.draw_object(&interpolated);
+        }
+    }
+
+    #[cfg(feature = "graphics")]
     pub fn draw (&self, gm: &mut GraphicsManager) {
         assert!(self.objects.len() == self.forces.len());
         assert!(self.objects.len() == self.torques.len());
@@ -98,3 +490,185 @@ impl ObjectManager {
         }
     }
 }
+
+impl Default for ObjectManager {
+    fn default () -> ObjectManager {
+        ObjectManager::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DT :f32 = 0.01f32;
+
+    fn push_sphere (manager: &mut ObjectManager, x: f32, y: f32, mass: f32) -> ObjectTag {
+        let mut object = Object::new();
+        object.position = Vector::new(x, y, 0f32, 1f32);
+        object.mass = mass;
+
+        let tag = manager.push_object_tagged(object);
+        manager.set_shape(Some(Shape::Sphere { radius: 0.5f32 }), &tag);
+        tag
+    }
+
+    fn push_box (manager: &mut ObjectManager, y: f32, mass: f32) -> ObjectTag {
+        let mut object = Object::new();
+        object.position = Vector::new(0f32, y, 0f32, 1f32);
+        object.mass = mass;
+        object.inertia = Matrix::scaling(mass / 6f32); //solid unit cube
+
+        let tag = manager.push_object_tagged(object);
+        manager.set_shape(Some(Shape::Box { half_extents: Vector::new(0.5f32, 0.5f32, 0.5f32, 0f32) }), &tag);
+        tag
+    }
+
+    //a step of the simulation with gravity on every movable object
+    fn step (manager: &mut ObjectManager, tags: &[ObjectTag]) {
+        for tag in tags {
+            let mass = manager.get_object(tag).mass;
+            if mass.is_finite() {
+                manager.apply_force(Vector::ey() * (-10f32 * mass), tag);
+            }
+        }
+        manager.update_physics(DT);
+    }
+
+    #[test]
+    fn sweep_only_reports_close_pairs () {
+        let mut manager = ObjectManager::new();
+
+        let far      = push_sphere(&mut manager, 5f32, 0f32, 1f32);
+        let right    = push_sphere(&mut manager, 0.9f32, 0f32, 1f32);
+        let left     = push_sphere(&mut manager, 0f32, 0f32, 1f32);
+        let above    = push_sphere(&mut manager, 0.5f32, 10f32, 1f32); //overlaps along x, not along y
+        let fixed    = push_sphere(&mut manager, 5.5f32, 0f32, f32::INFINITY);
+        let fixed_on = push_sphere(&mut manager, 6.2f32, 0f32, f32::INFINITY); //fixed pairs never collide
+        manager.push_object(Object::new()); //no shape
+
+        let contacts = manager.contacts(0.01f32);
+        assert!(contacts.len() == 2, "{} contacts", contacts.len());
+
+        //lower index first, the normal points from b to a
+        assert!(contacts.iter().any(|contact| contact.a == right && contact.b == Some(left) && contact.normal.x > 0.99f32));
+        assert!(contacts.iter().any(|contact| contact.a == far && contact.b == Some(fixed) && contact.normal.x < -0.99f32));
+
+        for contact in &contacts {
+            assert!(!contact.involves(&above) && !contact.involves(&fixed_on));
+        }
+    }
+
+    #[test]
+    fn dropped_box_comes_to_rest () {
+        let mut manager = ObjectManager::new();
+        manager.ground = Some(0f32);
+
+        let tag = push_box(&mut manager, 2f32, 2f32);
+
+        for _ in 0 .. 600 {
+            step(&mut manager, &[tag]);
+        }
+
+        let object = manager.get_object(&tag);
+        assert!(Vector::magnitude(object.velocity) < 1e-3f32, "still moving at {}", Vector::magnitude(object.velocity));
+        assert!((object.position.y - 0.5f32).abs() < 0.01f32, "rests at {}", object.position.y);
+
+        //the ground carries the weight, one step of it every step
+        let impulse = manager.get_contact_impulse(&tag);
+        assert!((impulse.y - 2f32 * 10f32 * DT).abs() < 1e-3f32, "contact impulse {}", impulse.y);
+        assert!(impulse.x.abs() < 1e-4f32 && impulse.z.abs() < 1e-4f32);
+    }
+
+    //highest point of the bottom of a ball dropped from 2 m, after its first bounce
+    fn bounce_height (restitution: f32) -> f32 {
+        let mut manager = ObjectManager::new();
+        manager.ground = Some(0f32);
+        manager.ground_surface.restitution = 0f32; //combined with the bouncier surface, leaves the ball's
+
+        let tag = push_sphere(&mut manager, 0f32, 2.5f32, 1f32);
+        manager.set_material(Material { restitution, friction: 0.5f32 }, &tag);
+
+        let mut bounced = false;
+        let mut height = 0f32;
+
+        for _ in 0 .. 200 {
+            step(&mut manager, &[tag]);
+
+            let object = manager.get_object(&tag);
+            bounced = bounced || object.velocity.y > 0f32;
+
+            if bounced {
+                height = height.max(object.position.y - 0.5f32);
+            }
+        }
+
+        height
+    }
+
+    #[test]
+    fn bounce_height_scales_with_restitution () {
+        let low  = bounce_height(0.3f32);
+        let high = bounce_height(0.6f32);
+
+        //restitution scales the speed, the height goes with its square
+        assert!((high - 0.36f32 * 2f32).abs() < 0.1f32, "bounced {} high", high);
+        assert!((high / low - 4f32).abs() < 0.5f32, "bounces of {} and {}", low, high);
+    }
+
+    #[test]
+    fn sliding_box_stops_under_friction () {
+        let mut manager = ObjectManager::new();
+        manager.ground = Some(0f32);
+
+        let tag = push_box(&mut manager, 0.5f32, 1f32);
+        manager.get_mut_object(&tag).velocity = Vector::ex() * 3f32;
+
+        for _ in 0 .. 600 {
+            step(&mut manager, &[tag]);
+        }
+
+        //friction 0.5 against the ground slows it by 5 m/s^2, that stops it after 0.9 m
+        let object = manager.get_object(&tag);
+        let sliding = (object.velocity.x * object.velocity.x + object.velocity.z * object.velocity.z).sqrt();
+        assert!(sliding < 1e-3f32, "still sliding at {}", sliding);
+        assert!((object.position.x - 0.9f32).abs() < 0.05f32, "slid {} m", object.position.x);
+    }
+
+    #[test]
+    fn fixed_objects_never_move () {
+        let mut manager = ObjectManager::new();
+        manager.ground = Some(0f32);
+
+        let fixed = push_box(&mut manager, 0.4f32, f32::INFINITY); //sunk into the ground a little, fixed objects ignore it
+        let ball  = push_sphere(&mut manager, 0.2f32, 3f32, 1f32);
+
+        for _ in 0 .. 300 {
+            manager.apply_force_torque(Vector::ey() * 100f32, Vector::ex() * 100f32, &fixed);
+            step(&mut manager, &[fixed, ball]);
+        }
+
+        let object = manager.get_object(&fixed);
+        assert!(object.position.x == 0f32 && object.position.y == 0.4f32 && object.position.z == 0f32);
+        assert!(Vector::magnitude(object.velocity) == 0f32 && Vector::magnitude(object.angular_velocity) == 0f32);
+
+        //the ball came to rest on top of it
+        assert!((manager.get_object(&ball).position.y - 1.4f32).abs() < 0.01f32, "ball at {}", manager.get_object(&ball).position.y);
+    }
+
+    #[test]
+    fn singular_inertia_only_takes_the_push () {
+        let mut object = Object::new();
+        object.mass = 2f32;
+        object.inertia = Matrix::scaling(0f32); //point mass
+
+        let response = Response::of(&object).unwrap();
+        let offset = Vector::new(0.5f32, 0.5f32, 0f32, 0f32);
+
+        assert!(Vector::magnitude(response.turn(Vector::ez())) == 0f32);
+        assert!(response.inverse_mass(offset, Vector::ey()) == 0.5f32);
+
+        object.mass = f32::INFINITY;
+        assert!(Response::of(&object).is_none());
+    }
+}
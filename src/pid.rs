@@ -0,0 +1,128 @@
+use vector::Vector;
+
+//pid on a vector error, the same gains for every component
+#[derive(Clone)]
+pub struct Pid {
+    pub kp:             f32,
+    pub ki:             f32,
+    pub kd:             f32,
+    pub integral_limit: f32, //anti-windup, magnitude of ki * integral never exceeds this
+    pub output_limit:   f32, //magnitude of the output never exceeds this
+    integral:           Vector,
+    previous_error:     Option<Vector>,
+}
+
+#[allow(dead_code)]
+impl Pid {
+    pub fn new (kp: f32, ki: f32, kd: f32, integral_limit: f32, output_limit: f32) -> Pid {
+        assert!(integral_limit >= 0f32);
+        assert!(output_limit >= 0f32);
+
+        Pid {
+            kp,
+            ki,
+            kd,
+            integral_limit,
+            output_limit,
+            integral:       Vector::null(),
+            previous_error: None,
+        }
+    }
+
+    pub fn reset (&mut self) {
+        self.integral = Vector::null();
+        self.previous_error = None;
+    }
+
+    pub fn integral (&self) -> Vector {
+        self.integral
+    }
+
+    pub fn update (&mut self, error: Vector, dt: f32) -> Vector {
+        assert!(error.w == 0f32);
+        assert!(dt > 0f32);
+
+        let derivative = match self.previous_error {
+            Some(previous_error) => (error - previous_error) / dt,
+            None => Vector::null(),
+        };
+        self.previous_error = Some(error);
+
+        let proportional_action = error * self.kp;
+        let derivative_action   = derivative * self.kd;
+
+        //only integrate while the output isn't saturated, or when integrating pulls it out of saturation
+        let unsaturated = proportional_action + derivative_action + self.integral * self.ki;
+        let saturated = Vector::magnitude(unsaturated) > self.output_limit;
+
+        if !saturated || Vector::dot(error, unsaturated) < 0f32 {
+            self.integral += error * dt;
+        }
+
+        if self.ki != 0f32 {
+            let integral_action = Vector::magnitude(self.integral * self.ki);
+
+            if integral_action > self.integral_limit {
+                self.integral *= self.integral_limit / integral_action;
+            }
+        }
+
+        let output = proportional_action + derivative_action + self.integral * self.ki;
+        let magnitude = Vector::magnitude(output);
+
+        if magnitude > self.output_limit {
+            output * (self.output_limit / magnitude)
+        } else {
+            output
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DT :f32 = 0.1f32;
+
+    #[test]
+    fn saturated_output_stops_the_integrator () {
+        let mut pid = Pid::new(1f32, 1f32, 0f32, 100f32, 2f32);
+
+        for _ in 0 .. 50 {
+            let output = pid.update(Vector::ex() * 5f32, DT);
+            assert!((Vector::magnitude(output) - 2f32).abs() < 1e-6f32);
+        }
+
+        assert_eq!(pid.integral().x, 0f32);
+    }
+
+    #[test]
+    fn integrator_winds_up_to_the_saturation_and_unwinds_right_away () {
+        let mut pid = Pid::new(0f32, 1f32, 0f32, 100f32, 2f32);
+
+        for _ in 0 .. 50 {
+            pid.update(Vector::ex(), DT);
+        }
+
+        //stops one step after the integral action reached the output limit
+        let wound = pid.integral().x;
+        assert!((2f32 ..= 2f32 + DT + 1e-5f32).contains(&wound), "integral {}", wound);
+
+        pid.update(-Vector::ex(), DT);
+        assert!((pid.integral().x - (wound - DT)).abs() < 1e-5f32);
+    }
+
+    #[test]
+    fn integral_action_is_clamped () {
+        let mut pid = Pid::new(0f32, 2f32, 0f32, 1f32, 10f32);
+
+        for _ in 0 .. 50 {
+            pid.update(Vector::new(1f32, -1f32, 0f32, 0f32), DT);
+        }
+
+        assert!((Vector::magnitude(pid.integral() * 2f32) - 1f32).abs() < 1e-5f32);
+
+        pid.reset();
+        assert_eq!(Vector::magnitude(pid.integral()), 0f32);
+    }
+}
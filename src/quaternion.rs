@@ -12,6 +12,15 @@ pub struct Quaternion {
 }
 
 impl Quaternion {
+    pub fn new (r :f32, i :f32, j :f32, k :f32) -> Quaternion {
+        Quaternion {
+            r,
+            i,
+            j,
+            k,
+        }
+    }
+
     pub fn identity () -> Quaternion {
         Quaternion {
             r: 1.0f32,
@@ -21,6 +30,10 @@ impl Quaternion {
         }       
     }
 
+    pub fn components (self) -> [f32; 4] {
+        [self.r, self.i, self.j, self.k]
+    }
+
     pub fn normalize (&mut self) {
         let len = (self.r * self.r + self.i * self.i + self.j * self.j + self.k * self.k).sqrt();
 
@@ -51,6 +64,68 @@ impl Quaternion {
         }
     }
 
+    //inverse rotation (for unit quaternions)
+    pub fn conjugate (self) -> Quaternion {
+        Quaternion {
+            r:  self.r,
+            i: -self.i,
+            j: -self.j,
+            k: -self.k,
+        }
+    }
+
+    //inverse of from_vector, returns axis * angle with angle in [0, pi]
+    pub fn to_vector (self) -> Vector {
+        let q = if self.r < 0.0f32 { //q and -q are the same rotation, take the short way around
+            Quaternion::new(-self.r, -self.i, -self.j, -self.k)
+        } else {
+            self
+        };
+
+        let sin_half_theta = (q.i * q.i + q.j * q.j + q.k * q.k).sqrt();
+
+        if sin_half_theta <= 0.0f32 {
+            return Vector::null();
+        }
+
+        let theta = 2.0f32 * sin_half_theta.atan2(q.r);
+
+        Vector::new(q.i, q.j, q.k, 0.0f32) * (theta / sin_half_theta)
+    }
+
+    //rotation a fraction t of the way from a to b, the short way around
+    pub fn slerp (a :Quaternion, b :Quaternion, t :f32) -> Quaternion {
+        let mut q = Quaternion::from_vector((b * a.conjugate()).to_vector() * t) * a;
+        q.normalize();
+        q
+    }
+
+    //inverse of to_matrix, m has to be a rotation matrix
+    pub fn from_matrix (m :Matrix) -> Quaternion {
+        let m00 = m.0[0].x; let m01 = m.0[1].x; let m02 = m.0[2].x;
+        let m10 = m.0[0].y; let m11 = m.0[1].y; let m12 = m.0[2].y;
+        let m20 = m.0[0].z; let m21 = m.0[1].z; let m22 = m.0[2].z;
+
+        let trace = m00 + m11 + m22;
+
+        let mut q = if trace > 0.0f32 {
+            let s = (trace + 1.0f32).sqrt() * 2.0f32; //4r
+            Quaternion::new(0.25f32 * s, (m21 - m12) / s, (m02 - m20) / s, (m10 - m01) / s)
+        } else if m00 > m11 && m00 > m22 {
+            let s = (1.0f32 + m00 - m11 - m22).sqrt() * 2.0f32; //4i
+            Quaternion::new((m21 - m12) / s, 0.25f32 * s, (m01 + m10) / s, (m02 + m20) / s)
+        } else if m11 > m22 {
+            let s = (1.0f32 + m11 - m00 - m22).sqrt() * 2.0f32; //4j
+            Quaternion::new((m02 - m20) / s, (m01 + m10) / s, 0.25f32 * s, (m12 + m21) / s)
+        } else {
+            let s = (1.0f32 + m22 - m00 - m11).sqrt() * 2.0f32; //4k
+            Quaternion::new((m10 - m01) / s, (m02 + m20) / s, (m12 + m21) / s, 0.25f32 * s)
+        };
+
+        q.normalize();
+        q
+    }
+
     pub fn to_matrix (self) -> Matrix {
         assert!(0.9999f32 < self.r * self.r + self.i * self.i + self.j * self.j + self.k * self.k);
         assert!(1.0001f32 > self.r * self.r + self.i * self.i + self.j * self.j + self.k * self.k);
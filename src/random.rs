@@ -0,0 +1,56 @@
+use rand;
+
+//small deterministic generator (splitmix64), a run can be replayed bit-for-bit from its seed
+#[derive(Clone)]
+pub struct Random {
+    seed:  u64,
+    state: u64,
+}
+
+#[allow(dead_code)]
+impl Random {
+    pub fn new (seed: u64) -> Random {
+        Random {
+            seed,
+            state: seed,
+        }
+    }
+
+    //picks a fresh seed, use seed() to store it
+    pub fn from_entropy () -> Random {
+        Random::new(rand::random::<u64>())
+    }
+
+    pub fn seed (&self) -> u64 {
+        self.seed
+    }
+
+    pub fn next_u64 (&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E3779B97F4A7C15);
+
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+
+    //uniform in [0, 1)
+    pub fn next_f32 (&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    //uniform in [low, high)
+    pub fn gen_range (&mut self, low: f32, high: f32) -> f32 {
+        assert!(low < high);
+
+        low + (high - low) * self.next_f32()
+    }
+
+    //standard normal, box-muller
+    pub fn gaussian (&mut self) -> f32 {
+        let u1 = 1f32 - self.next_f32(); //(0, 1], ln(0) is -inf
+        let u2 = self.next_f32();
+
+        (-2f32 * u1.ln()).sqrt() * (2f32 * ::std::f32::consts::PI * u2).cos()
+    }
+}
@@ -0,0 +1,453 @@
+use std::path::Path;
+use std::fs::File;
+use std::io::Write;
+use std::io::BufWriter;
+use std::io::Read;
+
+use vector::Vector;
+use quaternion::Quaternion;
+use drone::Wrench;
+use sensor::Readings;
+use estimator::Estimate;
+
+/* binary format, all numbers little endian;
+
+magic       8 bytes  "DRONEREC"
+version     u32
+rotors      u32      number of rotors of the drone, decides how many pwm columns there are
+columns     u32      number of f32 values per record
+records     columns * f32 each, in the order of columns(rotors), until the end of the file
+*/
+
+const MAGIC   :&[u8; 8] = b"DRONEREC";
+const VERSION :u32 = 8;
+
+const COLUMNS_BEFORE_PWM :[&str; 37] = [
+    "t",
+    "x", "y", "z",
+    "vx", "vy", "vz",
+    "qr", "qi", "qj", "qk",
+    "wx", "wy", "wz",
+    "ref_x", "ref_y", "ref_z",
+    "ref_vx", "ref_vy", "ref_vz",
+    "ref_ax", "ref_ay", "ref_az",
+    "ref_heading", "ref_heading_rate",
+    "cmd_ax", "cmd_ay", "cmd_az",
+    "cmd_thrust", "cmd_tx", "cmd_ty", "cmd_tz",
+    "alloc_thrust", "alloc_tx", "alloc_ty", "alloc_tz",
+    "saturated",
+];
+
+const COLUMNS_AFTER_PWM :[&str; 49] = [
+    "dist_fx", "dist_fy", "dist_fz",
+    "dist_tx", "dist_ty", "dist_tz",
+    "acc_x", "acc_y", "acc_z",
+    "gyro_x", "gyro_y", "gyro_z",
+    "baro",
+    "gps_x", "gps_y", "gps_z",
+    "gps_vx", "gps_vy", "gps_vz",
+    "mag_x", "mag_y", "mag_z",
+    "est_x", "est_y", "est_z",
+    "est_vx", "est_vy", "est_vz",
+    "est_qr", "est_qi", "est_qj", "est_qk",
+    "est_wx", "est_wy", "est_wz",
+    "est_err_position", "est_err_velocity", "est_err_attitude",
+    "wind_x", "wind_y", "wind_z",
+    "drag_fx", "drag_fy", "drag_fz",
+    "drag_tx", "drag_ty", "drag_tz",
+    "clearance", "collision",
+];
+
+//column names for a drone with the given number of rotors
+pub fn columns (rotors: usize) -> Vec<String> {
+    let mut columns :Vec<String> = COLUMNS_BEFORE_PWM.iter().map(|name| name.to_string()).collect();
+
+    for n in 1 .. rotors + 1 {
+        columns.push(format!("pwm{}_raw", n));
+    }
+    for n in 1 .. rotors + 1 {
+        columns.push(format!("pwm{}", n));
+    }
+
+    columns.extend(COLUMNS_AFTER_PWM.iter().map(|name| name.to_string()));
+
+    columns
+}
+
+pub fn column_count (rotors: usize) -> usize {
+    COLUMNS_BEFORE_PWM.len() + 2 * rotors + COLUMNS_AFTER_PWM.len()
+}
+
+//everything that happened to the drone during one simulation step
+#[derive(Clone)]
+pub struct Record {
+    pub t:                      f32,
+    pub position:               Vector,
+    pub velocity:               Vector,
+    pub rotation:               Quaternion,
+    pub angular_velocity:       Vector,
+    pub reference_position:     Vector,
+    pub reference_velocity:     Vector,
+    pub reference_acceleration: Vector,
+    pub reference_heading:      Option<(f32, f32)>, //heading and heading rate, NaN in the logs if the reference has none
+    pub commanded_acceleration: Vector,
+    pub commanded_wrench:       Wrench,   //what the attitude controller asked for
+    pub allocated_wrench:       Wrench,   //what the rotors got, differs from commanded_wrench while saturated
+    pub saturated:              bool,     //1 / 0 in the logs
+    pub pwm_raw:                Vec<f32>, //one per rotor, plain pseudo-inverse without motor limits, 0 .. 1 is the valid range
+    pub pwm:                    Vec<f32>, //after the priority allocation
+    pub disturbance_force:      Vector,
+    pub disturbance_torque:     Vector,
+    pub readings:               Readings, //what the sensors delivered so far
+    pub estimate:               Estimate, //what the controller used instead of the true state
+    pub wind:                   Vector,   //at the drone, world frame
+    pub drag_force:             Vector,
+    pub drag_torque:            Vector,
+    pub clearance:              f32,      //to the closest shape or the ground, inf if nothing is near
+    pub collision:              bool,     //touching a shape or the ground, 1 / 0 in the logs
+}
+
+#[allow(dead_code)]
+impl Record {
+    pub fn to_values (&self) -> Vec<f32> {
+        assert!(self.pwm_raw.len() == self.pwm.len());
+
+        let mut values = Vec::<f32>::with_capacity(column_count(self.pwm.len()));
+
+        values.push(self.t);
+        push_vector(&mut values, self.position);
+        push_vector(&mut values, self.velocity);
+        values.extend_from_slice(&self.rotation.components());
+        push_vector(&mut values, self.angular_velocity);
+        push_vector(&mut values, self.reference_position);
+        push_vector(&mut values, self.reference_velocity);
+        push_vector(&mut values, self.reference_acceleration);
+        match self.reference_heading {
+            Some((heading, heading_rate)) => {
+                values.push(heading);
+                values.push(heading_rate);
+            },
+            None => {
+                values.push(f32::NAN);
+                values.push(f32::NAN);
+            },
+        }
+        push_vector(&mut values, self.commanded_acceleration);
+        push_wrench(&mut values, self.commanded_wrench);
+        push_wrench(&mut values, self.allocated_wrench);
+        values.push(if self.saturated { 1f32 } else { 0f32 });
+        values.extend_from_slice(&self.pwm_raw);
+        values.extend_from_slice(&self.pwm);
+        push_vector(&mut values, self.disturbance_force);
+        push_vector(&mut values, self.disturbance_torque);
+        push_vector(&mut values, self.readings.accelerometer);
+        push_vector(&mut values, self.readings.gyroscope);
+        values.push(self.readings.barometer);
+        push_vector(&mut values, self.readings.gps_position);
+        push_vector(&mut values, self.readings.gps_velocity);
+        push_vector(&mut values, self.readings.magnetometer);
+        push_vector(&mut values, self.estimate.position);
+        push_vector(&mut values, self.estimate.velocity);
+        values.extend_from_slice(&self.estimate.rotation.components());
+        push_vector(&mut values, self.estimate.angular_velocity);
+        {
+            let (position_error, velocity_error, attitude_error) = self.estimation_errors();
+            values.push(position_error);
+            values.push(velocity_error);
+            values.push(attitude_error);
+        }
+        push_vector(&mut values, self.wind);
+        push_vector(&mut values, self.drag_force);
+        push_vector(&mut values, self.drag_torque);
+        values.push(self.clearance);
+        values.push(if self.collision { 1f32 } else { 0f32 });
+
+        assert!(values.len() == column_count(self.pwm.len()));
+
+        values
+    }
+
+    pub fn from_values (values: &[f32], rotors: usize) -> Record {
+        assert!(values.len() == column_count(rotors));
+
+        let pwm_start = COLUMNS_BEFORE_PWM.len();
+        let after_pwm = pwm_start + 2 * rotors;
+
+        let position = |n: usize| Vector::new(values[n], values[n + 1], values[n + 2], 1f32);
+        let vector   = |n: usize| Vector::new(values[n], values[n + 1], values[n + 2], 0f32);
+        let wrench   = |n: usize| Wrench {
+            thrust: values[n],
+            torque: vector(n + 1),
+        };
+
+        Record {
+            t:                      values[0],
+            position:               position(1),
+            velocity:               vector(4),
+            rotation:               Quaternion::new(values[7], values[8], values[9], values[10]),
+            angular_velocity:       vector(11),
+            reference_position:     position(14),
+            reference_velocity:     vector(17),
+            reference_acceleration: vector(20),
+            reference_heading:      if values[23].is_nan() { None } else { Some((values[23], values[24])) },
+            commanded_acceleration: vector(25),
+            commanded_wrench:       wrench(28),
+            allocated_wrench:       wrench(32),
+            saturated:              values[36] != 0f32,
+            pwm_raw:                values[pwm_start .. pwm_start + rotors].to_vec(),
+            pwm:                    values[pwm_start + rotors .. after_pwm].to_vec(),
+            disturbance_force:      vector(after_pwm),
+            disturbance_torque:     vector(after_pwm + 3),
+            readings:               Readings {
+                accelerometer: vector(after_pwm + 6),
+                gyroscope:     vector(after_pwm + 9),
+                barometer:     values[after_pwm + 12],
+                gps_position:  position(after_pwm + 13),
+                gps_velocity:  vector(after_pwm + 16),
+                magnetometer:  vector(after_pwm + 19),
+            },
+            estimate:               Estimate {
+                position:         position(after_pwm + 22),
+                velocity:         vector(after_pwm + 25),
+                rotation:         Quaternion::new(values[after_pwm + 28], values[after_pwm + 29], values[after_pwm + 30], values[after_pwm + 31]),
+                angular_velocity: vector(after_pwm + 32),
+            },
+            wind:                   vector(after_pwm + 38),
+            drag_force:             vector(after_pwm + 41),
+            drag_torque:            vector(after_pwm + 44),
+            clearance:              values[after_pwm + 47],
+            collision:              values[after_pwm + 48] != 0f32,
+        }
+    }
+
+    //distance of the estimate from the true position and velocity, and the angle between the rotations
+    pub fn estimation_errors (&self) -> (f32, f32, f32) {
+        (
+            Vector::magnitude(self.estimate.position - self.position),
+            Vector::magnitude(self.estimate.velocity - self.velocity),
+            Vector::magnitude((self.estimate.rotation * self.rotation.conjugate()).to_vector()),
+        )
+    }
+}
+
+fn push_vector (values: &mut Vec<f32>, v: Vector) {
+    values.push(v.x);
+    values.push(v.y);
+    values.push(v.z);
+}
+
+fn push_wrench (values: &mut Vec<f32>, w: Wrench) {
+    values.push(w.thrust);
+    push_vector(values, w.torque);
+}
+
+fn create_file (filename: &str) -> BufWriter<File> {
+    let path = Path::new(filename);
+
+    match File::create(path) {
+        Err(why) => {
+            println!("couldn't create {}: {}", path.display(), why);
+            panic!();
+        },
+        Ok(file) => BufWriter::new(file),
+    }
+}
+
+//writes records to csv and/or binary while the simulation runs
+pub struct Recorder {
+    rotors: usize,
+    csv:    Option<BufWriter<File>>,
+    binary: Option<BufWriter<File>>,
+}
+
+#[allow(dead_code)]
+impl Recorder {
+    pub fn new (rotors: usize, csv_filename: Option<&str>, binary_filename: Option<&str>) -> Recorder {
+        let mut recorder = Recorder {
+            rotors,
+            csv:    csv_filename.map(create_file),
+            binary: binary_filename.map(create_file),
+        };
+
+        if let Some(ref mut csv) = recorder.csv {
+            writeln!(csv, "{}", columns(rotors).join(",")).unwrap();
+        }
+
+        if let Some(ref mut binary) = recorder.binary {
+            binary.write_all(MAGIC).unwrap();
+            binary.write_all(&VERSION.to_le_bytes()).unwrap();
+            binary.write_all(&(rotors as u32).to_le_bytes()).unwrap();
+            binary.write_all(&(column_count(rotors) as u32).to_le_bytes()).unwrap();
+        }
+
+        recorder
+    }
+
+    pub fn record (&mut self, record: &Record) {
+        assert!(record.pwm.len() == self.rotors, "record has {} rotors, recorder expects {}", record.pwm.len(), self.rotors);
+
+        let values = record.to_values();
+
+        if let Some(ref mut csv) = self.csv {
+            let line :Vec<String> = values.iter().map(|value| value.to_string()).collect();
+            writeln!(csv, "{}", line.join(",")).unwrap();
+        }
+
+        if let Some(ref mut binary) = self.binary {
+            for value in &values {
+                binary.write_all(&value.to_bits().to_le_bytes()).unwrap();
+            }
+        }
+    }
+
+    pub fn flush (&mut self) {
+        if let Some(ref mut csv) = self.csv {
+            csv.flush().unwrap();
+        }
+
+        if let Some(ref mut binary) = self.binary {
+            binary.flush().unwrap();
+        }
+    }
+}
+
+fn read_u32 (data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+}
+
+pub fn read_binary (filename: &str) -> Vec<Record> {
+    let path = Path::new(filename);
+
+    let mut data = Vec::<u8>::new();
+
+    match File::open(path) {
+        Err(why) => {
+            println!("couldn't open {}: {}", path.display(), why);
+            panic!();
+        },
+        Ok(mut file) => {
+            if let Err(why) = file.read_to_end(&mut data) {
+                println!("couldn't read {}: {}", path.display(), why);
+                panic!();
+            }
+        },
+    };
+
+    assert!(data.len() >= 20 && &data[0 .. 8] == MAGIC, "{} is not a flight recording", path.display());
+    assert!(read_u32(&data, 8) == VERSION, "{} has an unsupported version", path.display());
+
+    let rotors = read_u32(&data, 12) as usize;
+    let column_count = column_count(rotors);
+    assert!(read_u32(&data, 16) as usize == column_count, "{} has an unexpected column count", path.display());
+
+    let record_size = column_count * 4;
+    let body = &data[20 ..];
+
+    let mut records = Vec::<Record>::new();
+    let mut values = Vec::<f32>::with_capacity(column_count);
+
+    for chunk in body.chunks(record_size) {
+        if chunk.len() < record_size {
+            break; //truncated last record, the run was probably killed
+        }
+
+        values.clear();
+        for n in 0 .. column_count {
+            values.push(f32::from_bits(read_u32(chunk, n * 4)));
+        }
+
+        let record = Record::from_values(&values, rotors);
+        assert!(record.t.is_finite(), "{} has a record at t = {}", path.display(), record.t);
+
+        records.push(record);
+    }
+
+    records
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROTORS :usize = 4;
+
+    //every column holds its own index, the flags are set
+    fn numbered_values () -> Vec<f32> {
+        columns(ROTORS).iter().enumerate().map(|(n, name)| match name.as_str() {
+            "saturated" | "collision" => 1f32,
+            _ => n as f32 + 0.5f32,
+        }).collect()
+    }
+
+    fn value (values: &[f32], name: &str) -> f32 {
+        values[columns(ROTORS).iter().position(|column| column == name).unwrap()]
+    }
+
+    fn same_bits (lhs: &[f32], rhs: &[f32]) -> bool {
+        lhs.len() == rhs.len() && lhs.iter().zip(rhs.iter()).all(|(a, b)| a.to_bits() == b.to_bits())
+    }
+
+    #[test]
+    fn fields_come_from_their_named_columns () {
+        let values = numbered_values();
+        let record = Record::from_values(&values, ROTORS);
+
+        assert_eq!(record.t,                                 value(&values, "t"));
+        assert_eq!(record.position.z,                        value(&values, "z"));
+        assert_eq!(record.rotation.components()[0],          value(&values, "qr"));
+        assert_eq!(record.angular_velocity.y,                value(&values, "wy"));
+        assert_eq!(record.reference_acceleration.x,          value(&values, "ref_ax"));
+        assert_eq!(record.reference_heading,                 Some((value(&values, "ref_heading"), value(&values, "ref_heading_rate"))));
+        assert_eq!(record.commanded_acceleration.z,          value(&values, "cmd_az"));
+        assert_eq!(record.commanded_wrench.torque.y,         value(&values, "cmd_ty"));
+        assert_eq!(record.allocated_wrench.thrust,           value(&values, "alloc_thrust"));
+        assert_eq!(record.pwm_raw[0],                        value(&values, "pwm1_raw"));
+        assert_eq!(record.pwm[2],                            value(&values, "pwm3"));
+        assert_eq!(record.disturbance_torque.z,              value(&values, "dist_tz"));
+        assert_eq!(record.readings.accelerometer.x,          value(&values, "acc_x"));
+        assert_eq!(record.readings.barometer,                value(&values, "baro"));
+        assert_eq!(record.readings.gps_position.y,           value(&values, "gps_y"));
+        assert_eq!(record.readings.magnetometer.z,           value(&values, "mag_z"));
+        assert_eq!(record.estimate.rotation.components()[3], value(&values, "est_qk"));
+        assert_eq!(record.estimate.angular_velocity.x,       value(&values, "est_wx"));
+        assert_eq!(record.wind.z,                            value(&values, "wind_z"));
+        assert_eq!(record.drag_torque.x,                     value(&values, "drag_tx"));
+        assert_eq!(record.clearance,                         value(&values, "clearance"));
+        assert!(record.saturated && record.collision);
+
+        //back to the same columns, except the estimation errors which are computed
+        let written = record.to_values();
+        for (n, name) in columns(ROTORS).iter().enumerate() {
+            if !name.starts_with("est_err") {
+                assert_eq!(written[n], values[n], "column {}", name);
+            }
+        }
+    }
+
+    #[test]
+    fn binary_recordings_read_back () {
+        let path = ::std::env::temp_dir().join(format!("dronesim_recorder_test_{}.bin", ::std::process::id()));
+        let filename = path.to_str().unwrap();
+
+        let first = Record::from_values(&numbered_values(), ROTORS);
+        let mut second = first.clone();
+        second.reference_heading = None;
+        second.saturated         = false;
+        second.clearance         = f32::INFINITY;
+
+        {
+            let mut recorder = Recorder::new(ROTORS, None, Some(filename));
+            recorder.record(&first);
+            recorder.record(&second);
+            recorder.flush();
+        }
+
+        let records = read_binary(filename);
+        ::std::fs::remove_file(&path).unwrap();
+
+        assert!(records.len() == 2);
+        assert!(same_bits(&records[0].to_values(), &first.to_values()));
+        assert!(same_bits(&records[1].to_values(), &second.to_values()));
+        assert!(records[1].reference_heading.is_none() && !records[1].saturated);
+    }
+}
@@ -0,0 +1,966 @@
+use std::cell::Cell;
+
+use utils;
+use vector::Vector;
+use matrix::Matrix;
+use spline::Spline;
+use spline::Bezier;
+use disturbance::Disturbance;
+use disturbance::Source;
+use disturbance::Frame;
+use disturbance::Trigger;
+use disturbance::Condition;
+use disturbance::Quantity;
+use aerodynamics::Drag;
+use collision::Shape;
+use collision::Material;
+use aerodynamics::Gust;
+use aerodynamics::Turbulence;
+use aerodynamics::TurbulenceModel;
+use evaluation::Criteria;
+use drone::AxisGains;
+use drone::DroneController;
+use drone::AttitudeMode;
+use drone::Airframe;
+use drone::Rotor;
+use drone::AllocationGroup;
+use motor::MotorModel;
+use controller::FlightController;
+use controller::MergeController;
+use controller::PidController;
+use pid::Pid;
+use integrator::Integrator;
+use scheduler::Task;
+use scheduler::Timing;
+use sensor::SensorKind;
+use sensor::NoiseModel;
+use sensor::DEFAULT_MAGNETIC_FIELD;
+use sensor::Sensors;
+use scheduler::Scheduler;
+use estimator::Estimator;
+use estimator::ComplementaryFilter;
+use estimator::ComplementaryGains;
+use estimator::Ekf;
+
+/* scenario file format;
+
+# comment
+[simulation]            <- section, optionally followed by a name: [object drone]
+dt = 0.02               <- key = value, vectors are whitespace separated numbers
+gravity = 0 -10 0
+
+sections: simulation, controller, schedule, sensor <kind>, estimator, airframe, wind, gust, collision, object <name>, bezier (in spline order), disturbance, evaluation
+*/
+
+pub const DEFAULT_DT         :f32 = 0.02f32;
+pub const DEFAULT_DURATION   :f32 = 60f32;
+pub const DEFAULT_MERGE_TIME :f32 = 1f32;
+pub const DEFAULT_ARM_LENGTH :f32 = ::std::f32::consts::SQRT_2; //rotors 1 away from the roll and pitch axes, like the original hardwired mixing
+pub const DEFAULT_NEAR_MISS  :f32 = 0.5f32; //m, shapes closer than this are reported even if they don't touch
+
+struct Entry {
+    key:   String,
+    value: String,
+    line:  usize,
+    read:  Cell<bool>, //set by the getters, whatever is left unread is a typo
+}
+
+pub struct Section {
+    pub kind: String,
+    pub name: Option<String>,
+    entries:  Vec<Entry>,
+    line:     usize, //line of the section header, for error messages
+}
+
+#[allow(dead_code)]
+impl Section {
+    pub fn get (&self, key: &str) -> Option<&str> {
+        for entry in &self.entries {
+            if entry.key == key {
+                entry.read.set(true);
+                return Some(entry.value.as_str());
+            }
+        }
+        None
+    }
+
+    //line of the entry for the key, for error messages about its value, the header if it isn't there
+    fn line_of (&self, key: &str) -> usize {
+        self.entries.iter().find(|entry| entry.key == key).map_or(self.line, |entry| entry.line)
+    }
+
+    //panics on the first key none of the getters asked for
+    pub fn check_read (&self) {
+        for (n, entry) in self.entries.iter().enumerate() {
+            if entry.read.get() {
+                continue;
+            }
+
+            if self.entries[.. n].iter().any(|earlier| earlier.key == entry.key) {
+                panic!("line {}: {} is set twice in [{}]", entry.line, entry.key, self.kind);
+            }
+
+            panic!("line {}: unknown key {} in [{}]", entry.line, entry.key, self.kind);
+        }
+    }
+
+    pub fn get_str (&self, key: &str, default: &str) -> String {
+        self.get(key).unwrap_or(default).to_string()
+    }
+
+    pub fn get_f32 (&self, key: &str, default: f32) -> f32 {
+        match self.get(key) {
+            Some(value) => self.parse_numbers(key, value, 1)[0],
+            None => default,
+        }
+    }
+
+    pub fn get_f32_option (&self, key: &str) -> Option<f32> {
+        self.get(key).map(|value| self.parse_numbers(key, value, 1)[0])
+    }
+
+    pub fn get_u64 (&self, key: &str) -> Option<u64> {
+        self.get(key).map(|value| match value.parse() {
+            Ok(number) => number,
+            Err(_) => panic!("[{}] at line {}: {} = {} is not an unsigned integer", self.kind, self.line_of(key), key, value),
+        })
+    }
+
+    pub fn get_bool (&self, key: &str, default: bool) -> bool {
+        match self.get(key) {
+            Some("true") => true,
+            Some("false") => false,
+            Some(value) => panic!("[{}] at line {}: {} = {} is not a bool", self.kind, self.line_of(key), key, value),
+            None => default,
+        }
+    }
+
+    //reads "x y z", the w component is taken from default
+    pub fn get_vector (&self, key: &str, default: Vector) -> Vector {
+        match self.get(key) {
+            Some(value) => {
+                let n = self.parse_numbers(key, value, 3);
+                Vector::new(n[0], n[1], n[2], default.w)
+            },
+            None => default,
+        }
+    }
+
+    //reads "x y z t", used for bezier control points
+    pub fn get_control_point (&self, key: &str) -> Vector {
+        match self.get(key) {
+            Some(value) => {
+                let n = self.parse_numbers(key, value, 4);
+                Vector::new(n[0], n[1], n[2], n[3])
+            },
+            None => panic!("[{}] at line {}: missing {}", self.kind, self.line, key),
+        }
+    }
+
+    //reads "h1 h2 h3 h4", the heading control values of a bezier
+    pub fn get_heading (&self, key: &str) -> Option<[f32; 4]> {
+        self.get(key).map(|value| {
+            let n = self.parse_numbers(key, value, 4);
+            [n[0], n[1], n[2], n[3]]
+        })
+    }
+
+    fn parse_numbers (&self, key: &str, value: &str, count: usize) -> Vec<f32> {
+        let mut numbers = Vec::<f32>::new();
+
+        for number_str in value.split_whitespace() {
+            match number_str.parse() {
+                Ok(number) => numbers.push(number),
+                Err(_) => panic!("[{}] at line {}: {} = {} is not a number", self.kind, self.line_of(key), key, value),
+            }
+        }
+
+        if numbers.len() != count {
+            panic!("[{}] at line {}: {} expects {} number(s), got {}", self.kind, self.line_of(key), key, count, numbers.len());
+        }
+
+        numbers
+    }
+}
+
+pub fn parse_sections (text: &str) -> Vec<Section> {
+    let mut sections = Vec::<Section>::new();
+
+    for (n, raw_line) in text.lines().enumerate() {
+        let line = raw_line.split('#').next().unwrap().trim();
+
+        if line.is_empty() {
+            continue;
+        }
+
+        if line.starts_with('[') && line.ends_with(']') {
+            let mut words = line[1 .. line.len() - 1].split_whitespace();
+
+            let kind = match words.next() {
+                Some(kind) => kind.to_string(),
+                None => panic!("line {}: empty section header", n + 1),
+            };
+
+            sections.push(
+                Section {
+                    kind,
+                    name: words.next().map(|name| name.to_string()),
+                    entries: Vec::<Entry>::new(),
+                    line: n + 1,
+                }
+            );
+        } else if let Some(split) = line.find('=') {
+            let key   = line[.. split].trim().to_string();
+            let value = line[split + 1 ..].trim().to_string();
+
+            match sections.last_mut() {
+                Some(section) => section.entries.push(Entry {
+                    key,
+                    value,
+                    line:  n + 1,
+                    read:  Cell::new(false),
+                }),
+                None => panic!("line {}: {} outside of a section", n + 1, key),
+            }
+        } else {
+            panic!("line {}: expected [section] or key = value, got \"{}\"", n + 1, line);
+        }
+    }
+
+    sections
+}
+
+pub enum Role {
+    Drone,
+    Target(f32), //follows the reference spline, the value is how far ahead in time
+    Static,
+}
+
+pub enum ControllerType {
+    Merge, //controller::MergeController
+    Pid,   //controller::PidController
+}
+
+pub enum EstimatorType {
+    Truth,         //the controller sees the true state
+    Complementary, //estimator::ComplementaryFilter
+    Ekf,           //estimator::Ekf
+}
+
+pub struct ObjectDescription {
+    pub name:               String,
+    pub role:               Role,
+    pub model:              Option<String>,
+    pub scale:              f32,
+    pub mass:               f32,
+    pub inertia:            Matrix, //body frame tensor
+    pub position:           Vector,
+    pub velocity:           Vector,
+    pub rotation:           Vector, //axis * angle
+    pub angular_velocity:   Vector,
+    pub start_on_reference: bool,   //overrides position and velocity with the start of the reference spline
+    pub drag:               Drag,   //body frame, relative to the wind
+    pub shape:              Option<Shape>, //None -> doesn't collide
+    pub material:           Material,
+}
+
+pub struct Scenario {
+    pub dt:             f32,
+    pub duration:       f32,
+    pub merge_time:     f32,
+    pub gravity:        Vector,
+    pub seed:           Option<u64>, //None -> pick a random seed
+    pub integrator:     Integrator,
+    pub controller:     ControllerType,
+    pub schedule:       Vec<(Task, Timing)>, //tasks that don't run every physics step
+    pub sensors:        Vec<(SensorKind, NoiseModel)>, //sensors that aren't perfect
+    pub magnetic_field: Vector, //world frame, what the magnetometer measures
+    pub estimator:      EstimatorType,
+    pub complementary:  ComplementaryGains,
+    pub attitude:       DroneController, //gains of the attitude loop
+    pub heading:        f32, //radians around world y, only used by the geometric attitude mode when the spline has no heading profile
+    pub position_pid:   Pid,
+    pub velocity_pid:   Pid,
+    pub airframe:       Airframe, //rotor layout and motors of the drone
+    pub wind:           Vector, //mean wind, world frame
+    pub gusts:          Vec<Gust>,
+    pub turbulence:     Turbulence,
+    pub ground:         Option<f32>, //height of the ground plane, None -> no ground
+    pub ground_surface: Material,
+    pub near_miss:      f32,
+    pub response:       bool, //false -> collisions are only detected
+    pub objects:        Vec<ObjectDescription>,
+    pub spline:         Spline,
+    pub disturbances:   Vec<(String, Disturbance)>, //name of the object it acts on
+    pub criteria:       Criteria,
+}
+
+#[allow(dead_code)]
+impl Scenario {
+    pub fn build_controller (&self) -> Box<dyn FlightController> {
+        match self.controller {
+            ControllerType::Merge => Box::new(MergeController::new(self.merge_time, self.attitude, self.heading)),
+            ControllerType::Pid   => Box::new(PidController::new(self.position_pid.clone(), self.velocity_pid.clone(), self.attitude, self.heading)),
+        }
+    }
+
+    //None -> the controller flies on the true state
+    pub fn build_estimator (&self, sensors: &Sensors, scheduler: &Scheduler) -> Option<Box<dyn Estimator>> {
+        match self.estimator {
+            EstimatorType::Truth         => None,
+            EstimatorType::Complementary => Some(Box::new(ComplementaryFilter::new(self.complementary, self.gravity, sensors.magnetic_field))),
+            EstimatorType::Ekf           => Some(Box::new(Ekf::new(sensors, scheduler, self.gravity))),
+        }
+    }
+
+    pub fn load (filename: &str) -> Scenario {
+        Scenario::parse(utils::read_file(filename).as_str())
+    }
+
+    pub fn parse (text: &str) -> Scenario {
+        let mut scenario = Scenario {
+            dt:             DEFAULT_DT,
+            duration:       DEFAULT_DURATION,
+            merge_time:     DEFAULT_MERGE_TIME,
+            gravity:        Vector::ey() * -10f32,
+            seed:           None,
+            integrator:     Integrator::SemiImplicitEuler,
+            controller:     ControllerType::Merge,
+            schedule:       Vec::<(Task, Timing)>::new(),
+            sensors:        Vec::<(SensorKind, NoiseModel)>::new(),
+            magnetic_field: DEFAULT_MAGNETIC_FIELD,
+            estimator:      EstimatorType::Truth,
+            complementary:  ComplementaryGains::new(),
+            attitude:       DroneController::new(),
+            heading:        0f32,
+            position_pid:   Pid::new(2f32, 0f32, 0f32, 0f32, 5f32),
+            velocity_pid:   Pid::new(5f32, 2f32, 0f32, 5f32, 20f32),
+            airframe:       Airframe::quad_x(DEFAULT_ARM_LENGTH, MotorModel::new()),
+            wind:           Vector::null(),
+            gusts:          Vec::<Gust>::new(),
+            turbulence:     Turbulence::new(),
+            ground:         None,
+            ground_surface: Material::new(),
+            near_miss:      DEFAULT_NEAR_MISS,
+            response:       true,
+            objects:        Vec::<ObjectDescription>::new(),
+            spline:         Spline::new(),
+            disturbances:   Vec::<(String, Disturbance)>::new(),
+            criteria:       Criteria::new(),
+        };
+
+        let mut spline_end = Option::None::<Vector>; //last control point so far
+
+        for section in parse_sections(text) {
+            match section.kind.as_str() {
+                "simulation" => {
+                    scenario.dt         = section.get_f32("dt", scenario.dt);
+                    scenario.duration   = section.get_f32("duration", scenario.duration);
+                    scenario.gravity    = section.get_vector("gravity", scenario.gravity);
+                    scenario.seed       = section.get_u64("seed").or(scenario.seed);
+
+                    if let Some(name) = section.get("integrator") {
+                        scenario.integrator = match Integrator::from_name(name) {
+                            Some(integrator) => integrator,
+                            None => panic!("line {}: unknown integrator \"{}\", expected semi_implicit_euler, rk4, verlet or lie_group", section.line, name),
+                        };
+                    }
+                },
+                "controller" => {
+                    scenario.controller = match section.get_str("type", "merge").as_str() {
+                        "merge" => ControllerType::Merge,
+                        "pid"   => ControllerType::Pid,
+                        other   => panic!("line {}: unknown controller type \"{}\"", section.line, other),
+                    };
+                    scenario.merge_time = section.get_f32("merge_time", scenario.merge_time);
+                    scenario.heading    = section.get_f32("heading", scenario.heading);
+                    scenario.attitude   = Scenario::parse_attitude(&section, &scenario.attitude);
+                    scenario.position_pid = Scenario::parse_pid(&section, "position", &scenario.position_pid);
+                    scenario.velocity_pid = Scenario::parse_pid(&section, "velocity", &scenario.velocity_pid);
+                },
+                "schedule" => {
+                    Scenario::parse_schedule(&section, &mut scenario.schedule);
+                },
+                "sensor" => {
+                    Scenario::parse_sensor(&section, &mut scenario);
+                },
+                "estimator" => {
+                    scenario.estimator = match section.get_str("type", "truth").as_str() {
+                        "truth"         => EstimatorType::Truth,
+                        "complementary" => EstimatorType::Complementary,
+                        "ekf"           => EstimatorType::Ekf,
+                        other           => panic!("line {}: unknown estimator type \"{}\", expected truth, complementary or ekf", section.line, other),
+                    };
+                    scenario.complementary = Scenario::parse_complementary(&section, &scenario.complementary);
+                },
+                "airframe" => {
+                    scenario.airframe = Scenario::parse_airframe(&section, &scenario.airframe);
+                },
+                "wind" => {
+                    scenario.wind       = section.get_vector("velocity", scenario.wind);
+                    scenario.turbulence = Scenario::parse_turbulence(&section, &scenario.turbulence);
+                },
+                "gust" => {
+                    scenario.gusts.push(Gust {
+                        start:    section.get_f32("start", 0f32),
+                        duration: section.get_f32("duration", 1f32),
+                        velocity: section.get_vector("velocity", Vector::null()),
+                    });
+                },
+                "collision" => {
+                    scenario.ground    = section.get_f32_option("ground").or(scenario.ground);
+                    scenario.near_miss = section.get_f32("near_miss", scenario.near_miss);
+                    scenario.response  = section.get_bool("response", scenario.response);
+                    scenario.ground_surface = Scenario::parse_material(&section, "ground_", scenario.ground_surface);
+                },
+                "object" => {
+                    scenario.objects.push(Scenario::parse_object(&section));
+                },
+                "bezier" => {
+                    let p1 = section.get_control_point("p1");
+                    let p4 = section.get_control_point("p4");
+
+                    match spline_end {
+                        None => assert!(p1.w == 0f32, "line {}: the first bezier has to start at t = 0, not {}", section.line, p1.w),
+                        Some(end) => assert!(
+                            p1.x == end.x && p1.y == end.y && p1.z == end.z && p1.w == end.w,
+                            "line {}: p1 has to be p4 of the previous bezier", section.line
+                        ),
+                    }
+                    spline_end = Some(p4);
+
+                    let mut curve = Bezier::new(
+                        p1,
+                        section.get_control_point("p2"),
+                        section.get_control_point("p3"),
+                        p4,
+                    );
+
+                    if let Some(h) = section.get_heading("heading") {
+                        curve = curve.with_heading(h[0], h[1], h[2], h[3]);
+                    }
+
+                    scenario.spline.push_curve(curve);
+                },
+                "disturbance" => {
+                    let object = section.get_str("object", "drone");
+                    scenario.disturbances.push((object, Scenario::parse_disturbance(&section)));
+                },
+                "evaluation" => {
+                    scenario.criteria = Scenario::parse_criteria(&section, &scenario.criteria);
+                },
+                _ => panic!("line {}: unknown section [{}]", section.line, section.kind),
+            }
+
+            section.check_read();
+        }
+
+        assert!(spline_end.is_some(), "the scenario has no [bezier] section, it needs a reference spline");
+
+        scenario
+    }
+
+    //<task>_period, <task>_phase and <task>_latency in seconds, tasks without any of them keep running every step
+    fn parse_schedule (section: &Section, schedule: &mut Vec<(Task, Timing)>) {
+        for &task in Task::all().iter() {
+            let period  = section.get_f32_option(format!("{}_period", task.name()).as_str());
+            let phase   = section.get_f32_option(format!("{}_phase", task.name()).as_str());
+            let latency = section.get_f32_option(format!("{}_latency", task.name()).as_str());
+
+            if period.is_none() && phase.is_none() && latency.is_none() {
+                continue;
+            }
+
+            let timing = Timing::new(period.unwrap_or(0f32), phase.unwrap_or(0f32), latency.unwrap_or(0f32));
+
+            schedule.retain(|entry| entry.0 != task);
+            schedule.push((task, timing));
+        }
+    }
+
+    //[sensor <kind>], noise model and timing of one sensor
+    fn parse_sensor (section: &Section, scenario: &mut Scenario) {
+        let name = match section.name {
+            Some(ref name) => name.clone(),
+            None => panic!("line {}: [sensor] needs a kind", section.line),
+        };
+
+        let kind = match SensorKind::from_name(name.as_str()) {
+            Some(kind) => kind,
+            None => panic!("line {}: unknown sensor \"{}\", expected accelerometer, gyroscope, barometer, gps_position, gps_velocity or magnetometer", section.line, name),
+        };
+
+        let mut model = match scenario.sensors.iter().find(|entry| entry.0 == kind) {
+            Some(entry) => entry.1,
+            None => NoiseModel::new(),
+        };
+
+        model.noise        = section.get_f32("noise", model.noise);
+        model.bias         = section.get_f32("bias", model.bias);
+        model.bias_walk    = section.get_f32("bias_walk", model.bias_walk);
+        model.quantization = section.get_f32("quantization", model.quantization);
+
+        scenario.sensors.retain(|entry| entry.0 != kind);
+        scenario.sensors.push((kind, model));
+
+        if kind == SensorKind::Magnetometer {
+            scenario.magnetic_field = section.get_vector("field", scenario.magnetic_field);
+        }
+
+        //same as <kind>_period, <kind>_phase and <kind>_latency in [schedule]
+        let period  = section.get_f32_option("period");
+        let phase   = section.get_f32_option("phase");
+        let latency = section.get_f32_option("latency");
+
+        if period.is_some() || phase.is_some() || latency.is_some() {
+            let timing = Timing::new(period.unwrap_or(0f32), phase.unwrap_or(0f32), latency.unwrap_or(0f32));
+
+            scenario.schedule.retain(|entry| entry.0 != Task::Sensor(kind));
+            scenario.schedule.push((Task::Sensor(kind), timing));
+        }
+    }
+
+    fn parse_object (section: &Section) -> ObjectDescription {
+        let name = match section.name {
+            Some(ref name) => name.clone(),
+            None => panic!("line {}: [object] needs a name", section.line),
+        };
+
+        let role = match section.get_str("role", "static").as_str() {
+            "drone"  => Role::Drone,
+            "target" => Role::Target(section.get_f32("lead", 0f32)),
+            "static" => Role::Static,
+            other    => panic!("[object {}]: unknown role {}", name, other),
+        };
+
+        ObjectDescription {
+            name,
+            role,
+            model:              section.get("model").map(|model| model.to_string()),
+            scale:              section.get_f32("scale", 1f32),
+            mass:               section.get_f32("mass", 1f32),
+            inertia:            Scenario::parse_inertia(section),
+            position:           section.get_vector("position", Vector::origin()),
+            velocity:           section.get_vector("velocity", Vector::null()),
+            rotation:           section.get_vector("rotation", Vector::null()),
+            angular_velocity:   section.get_vector("angular_velocity", Vector::null()),
+            start_on_reference: section.get_bool("start_on_reference", false),
+            drag:               Drag {
+                linear:    section.get_vector("drag_linear", Vector::null()),
+                quadratic: section.get_vector("drag_quadratic", Vector::null()),
+                angular:   section.get_vector("drag_angular", Vector::null()),
+            },
+            shape:              Scenario::parse_shape(section),
+            material:           Scenario::parse_material(section, "", Material::new()),
+        }
+    }
+
+    //<prefix>restitution in 0 .. 1 and <prefix>friction >= 0
+    fn parse_material (section: &Section, prefix: &str, defaults: Material) -> Material {
+        let restitution = section.get_f32((prefix.to_string() + "restitution").as_str(), defaults.restitution);
+        let friction    = section.get_f32((prefix.to_string() + "friction").as_str(), defaults.friction);
+
+        assert!((0f32 ..= 1f32).contains(&restitution), "line {}: {}restitution must be within 0 .. 1", section.line, prefix);
+        assert!(friction >= 0f32, "line {}: {}friction must not be negative", section.line, prefix);
+
+        Material {
+            restitution,
+            friction,
+        }
+    }
+
+    //shape = sphere (radius) | box (half_extents = x y z) | capsule (radius, half_length along body y) | hull (of the model, scaled)
+    //in meters in the body frame, without shape the object doesn't collide
+    fn parse_shape (section: &Section) -> Option<Shape> {
+        let positive = |key: &str, default: f32| {
+            let value = section.get_f32(key, default);
+            assert!(value > 0f32, "line {}: {} must be positive", section.line, key);
+            value
+        };
+
+        match section.get("shape") {
+            None => None,
+            Some("sphere") => Some(Shape::Sphere {
+                radius: positive("radius", 0.5f32),
+            }),
+            Some("box") => {
+                let half_extents = section.get_vector("half_extents", Vector::new(0.5f32, 0.5f32, 0.5f32, 0f32));
+                assert!(half_extents.x > 0f32 && half_extents.y > 0f32 && half_extents.z > 0f32, "line {}: half_extents must be positive", section.line);
+
+                Some(Shape::Box { half_extents })
+            },
+            Some("capsule") => Some(Shape::Capsule {
+                radius:      positive("radius", 0.5f32),
+                half_length: section.get_f32("half_length", 0.5f32),
+            }),
+            Some("hull") => match section.get("model") {
+                Some(model) => Some(Shape::hull_from_model(model, section.get_f32("scale", 1f32))),
+                None => panic!("line {}: shape = hull needs a model", section.line),
+            },
+            Some(other) => panic!("line {}: unknown shape \"{}\", expected sphere, box, capsule or hull", section.line, other),
+        }
+    }
+
+    //angular_inertia = 1 for the same inertia around every axis, or inertia = ixx iyy izz (principal moments, takes precedence)
+    //with optional inertia_products = ixy ixz iyz
+    fn parse_inertia (section: &Section) -> Matrix {
+        let isotropic = section.get_f32("angular_inertia", 600f32);
+
+        let diagonal = section.get_vector("inertia", Vector::new(isotropic, isotropic, isotropic, 0f32));
+        let products = section.get_vector("inertia_products", Vector::null());
+
+        let inertia = Matrix::symmetric(diagonal.x, diagonal.y, diagonal.z, products.x, products.y, products.z);
+        assert!(inertia.inverse().is_some(), "line {}: inertia tensor is singular", section.line);
+
+        inertia
+    }
+
+    //beta and alpha set all axes, roll_beta, pitch_alpha, ... override a single axis
+    fn parse_attitude (section: &Section, defaults: &DroneController) -> DroneController {
+        let parse_axis = |axis: &str, default: AxisGains| {
+            let beta_key = format!("{}_beta", axis);
+            let beta = section.get_f32(beta_key.as_str(), section.get_f32("beta", default.beta));
+
+            let alpha = if section.get(beta_key.as_str()).is_some() || section.get("beta").is_some() {
+                section.get_f32("alpha", beta * beta / 4f32) //critically damped unless given
+            } else {
+                section.get_f32("alpha", default.alpha)
+            };
+
+            AxisGains::new(
+                section.get_f32(format!("{}_alpha", axis).as_str(), alpha),
+                beta,
+            )
+        };
+
+        DroneController {
+            roll:         parse_axis("roll", defaults.roll),
+            pitch:        parse_axis("pitch", defaults.pitch),
+            yaw:          parse_axis("yaw", defaults.yaw),
+            thrust_scale: section.get_f32("thrust_scale", defaults.thrust_scale),
+            mode:         match section.get("attitude") {
+                Some("tilt")      => AttitudeMode::Tilt,
+                Some("geometric") => AttitudeMode::Geometric,
+                Some(other)       => panic!("line {}: unknown attitude mode \"{}\"", section.line, other),
+                None              => defaults.mode,
+            },
+        }
+    }
+
+    //position_kp, position_ki, position_kd, position_integral_limit, position_limit, same for velocity
+    fn parse_pid (section: &Section, prefix: &str, defaults: &Pid) -> Pid {
+        let key = |name: &str| format!("{}_{}", prefix, name);
+
+        Pid::new(
+            section.get_f32(key("kp").as_str(), defaults.kp),
+            section.get_f32(key("ki").as_str(), defaults.ki),
+            section.get_f32(key("kd").as_str(), defaults.kd),
+            section.get_f32(key("integral_limit").as_str(), defaults.integral_limit),
+            section.get_f32(key("limit").as_str(), defaults.output_limit),
+        )
+    }
+
+    //frame = quad_x | quad_plus | hexa | octo | coaxial | custom, custom frames list rotor1 = x y z spin, rotor2 = ...,
+    //with an optional rotor1_axis = x y z (default 0 1 0), priority is the order in which the allocation gives up parts of the wrench
+    fn parse_airframe (section: &Section, defaults: &Airframe) -> Airframe {
+        let mut motor = defaults.motor;
+
+        motor.spin_up_time       = section.get_f32("spin_up_time", motor.spin_up_time);
+        motor.spin_down_time     = section.get_f32("spin_down_time", motor.spin_down_time);
+        motor.max_rpm            = section.get_f32("max_rpm", motor.max_rpm);
+        motor.idle_pwm           = section.get_f32("idle_pwm", motor.idle_pwm);
+        motor.thrust_coefficient = section.get_f32("thrust_coefficient", motor.thrust_coefficient);
+        motor.drag_coefficient   = section.get_f32("drag_coefficient", motor.drag_coefficient);
+
+        //max_thrust is the friendlier way to give the thrust coefficient
+        if let Some(max_thrust) = section.get_f32_option("max_thrust") {
+            motor.thrust_coefficient = max_thrust / (motor.max_rpm * motor.max_rpm);
+        }
+
+        assert!(motor.max_rpm > 0f32 && motor.thrust_coefficient > 0f32, "line {}: max_rpm and the thrust coefficient must be positive", section.line);
+
+        let arm_length = section.get_f32("arm_length", DEFAULT_ARM_LENGTH);
+
+        let mut airframe = match section.get("frame") {
+            None if section.get("arm_length").is_some() => Airframe::quad_x(arm_length, motor),
+            None              => Airframe::new(defaults.rotors.clone(), motor),
+            Some("quad_x")    => Airframe::quad_x(arm_length, motor),
+            Some("quad_plus") => Airframe::quad_plus(arm_length, motor),
+            Some("hexa")      => Airframe::hexa(arm_length, motor),
+            Some("octo")      => Airframe::octo(arm_length, motor),
+            Some("coaxial")   => Airframe::coaxial(arm_length, section.get_f32("spacing", 0.1f32 * arm_length), motor),
+            Some("custom")    => {
+                let mut rotors = Vec::<Rotor>::new();
+
+                for n in 1 .. {
+                    let key = format!("rotor{}", n);
+                    if section.get(key.as_str()).is_none() {
+                        break;
+                    }
+
+                    let r = section.get_control_point(key.as_str());
+                    let axis = section.get_vector(format!("{}_axis", key).as_str(), Vector::ey());
+
+                    rotors.push(Rotor::new(Vector::new(r.x, r.y, r.z, 0f32), axis, r.w));
+                }
+
+                assert!(rotors.len() >= 4, "line {}: a custom frame needs at least rotor1 .. rotor4", section.line);
+
+                Airframe::new(rotors, motor)
+            },
+            Some(other) => panic!("line {}: unknown frame \"{}\"", section.line, other),
+        };
+
+        airframe.priority = defaults.priority;
+
+        //priority = attitude yaw thrust, most important first
+        if let Some(value) = section.get("priority") {
+            let groups :Vec<AllocationGroup> = value.split_whitespace().map(|word| match word {
+                "attitude" => AllocationGroup::Attitude,
+                "yaw"      => AllocationGroup::Yaw,
+                "thrust"   => AllocationGroup::Thrust,
+                other      => panic!("line {}: unknown allocation group \"{}\"", section.line, other),
+            }).collect();
+
+            let complete = groups.len() == 3 &&
+                           groups.contains(&AllocationGroup::Attitude) &&
+                           groups.contains(&AllocationGroup::Yaw) &&
+                           groups.contains(&AllocationGroup::Thrust);
+            assert!(complete, "line {}: priority has to list attitude, yaw and thrust once each", section.line);
+
+            airframe.priority = [groups[0], groups[1], groups[2]];
+        }
+
+        airframe
+    }
+
+    //turbulence = off | dryden | von_karman, intensity and length_scale are longitudinal lateral vertical
+    fn parse_turbulence (section: &Section, defaults: &Turbulence) -> Turbulence {
+        let model = match section.get("turbulence") {
+            Some(name) => match TurbulenceModel::from_name(name) {
+                Some(model) => model,
+                None => panic!("line {}: unknown turbulence \"{}\", expected off, dryden or von_karman", section.line, name),
+            },
+            None => defaults.model,
+        };
+
+        let turbulence = Turbulence {
+            model,
+            intensity:    section.get_vector("intensity", defaults.intensity),
+            length_scale: section.get_vector("length_scale", defaults.length_scale),
+            airspeed:     section.get_f32_option("airspeed").or(defaults.airspeed),
+        };
+
+        let l = turbulence.length_scale;
+        assert!(l.x > 0f32 && l.y > 0f32 && l.z > 0f32, "line {}: length_scale must be positive", section.line);
+
+        turbulence
+    }
+
+    //<name>_gain, only used by the complementary filter
+    fn parse_complementary (section: &Section, defaults: &ComplementaryGains) -> ComplementaryGains {
+        ComplementaryGains {
+            tilt:     section.get_f32("tilt_gain", defaults.tilt),
+            heading:  section.get_f32("heading_gain", defaults.heading),
+            bias:     section.get_f32("bias_gain", defaults.bias),
+            position: section.get_f32("position_gain", defaults.position),
+            velocity: section.get_f32("velocity_gain", defaults.velocity),
+            altitude: section.get_f32("altitude_gain", defaults.altitude),
+        }
+    }
+
+    fn parse_criteria (section: &Section, defaults: &Criteria) -> Criteria {
+        //with the default thresholds of 0 any constant or random disturbance keeps an event going for the whole run
+        if section.get("max_convergence_time").is_some() {
+            assert!(
+                section.get("force_threshold").is_some() && section.get("torque_threshold").is_some(),
+                "line {}: max_convergence_time needs force_threshold and torque_threshold, the disturbance sizes that start an event", section.line_of("max_convergence_time")
+            );
+        }
+
+        Criteria {
+            band:                        section.get_f32("band", defaults.band),
+            force_threshold:             section.get_f32("force_threshold", defaults.force_threshold),
+            torque_threshold:            section.get_f32("torque_threshold", defaults.torque_threshold),
+            max_position_rms:            section.get_f32_option("max_position_rms").or(defaults.max_position_rms),
+            max_position_error:          section.get_f32_option("max_position_error").or(defaults.max_position_error),
+            max_velocity_rms:            section.get_f32_option("max_velocity_rms").or(defaults.max_velocity_rms),
+            max_velocity_error:          section.get_f32_option("max_velocity_error").or(defaults.max_velocity_error),
+            max_settling_time:           section.get_f32_option("max_settling_time").or(defaults.max_settling_time),
+            max_convergence_time:        section.get_f32_option("max_convergence_time").or(defaults.max_convergence_time),
+            max_control_effort:          section.get_f32_option("max_control_effort").or(defaults.max_control_effort),
+            max_saturation_time:         section.get_f32_option("max_saturation_time").or(defaults.max_saturation_time),
+            max_estimation_position_rms: section.get_f32_option("max_estimation_position_rms").or(defaults.max_estimation_position_rms),
+            max_estimation_attitude_rms: section.get_f32_option("max_estimation_attitude_rms").or(defaults.max_estimation_attitude_rms),
+            max_collisions:              section.get_u64("max_collisions").map(|limit| limit as usize).or(defaults.max_collisions),
+            min_clearance:               section.get_f32_option("min_clearance").or(defaults.min_clearance),
+        }
+    }
+
+    //kind with its values, frame = world | body, active from start until end (seconds) while when = <quantity> < | > <value> holds
+    fn parse_disturbance (section: &Section) -> Disturbance {
+        let source = match section.get_str("kind", "").as_str() {
+            "random_force" => Source::RandomForce {
+                magnitude: section.get_f32("magnitude", 0f32),
+            },
+            "random_torque" => Source::RandomTorque {
+                magnitude: section.get_f32("magnitude", 0f32),
+            },
+            "impulse" => Source::Impulse {
+                impulse:         section.get_vector("impulse", Vector::null()),
+                angular_impulse: section.get_vector("angular_impulse", Vector::null()),
+            },
+            "step" => Source::Step {
+                force:  section.get_vector("force", Vector::null()),
+                torque: section.get_vector("torque", Vector::null()),
+            },
+            "periodic" => Source::Periodic {
+                force:    section.get_vector("force", Vector::null()),
+                torque:   section.get_vector("torque", Vector::null()),
+                period:   section.get_f32("period", 1f32),
+                duration: section.get_f32("duration", 0f32),
+            },
+            "random_walk" => Source::RandomWalk {
+                force:        section.get_f32("force_deviation", 0f32),
+                torque:       section.get_f32("torque_deviation", 0f32),
+                force_limit:  section.get_f32("force_limit", f32::INFINITY),
+                torque_limit: section.get_f32("torque_limit", f32::INFINITY),
+            },
+            "noise" => Source::Noise {
+                force:     section.get_f32("force_deviation", 0f32),
+                torque:    section.get_f32("torque_deviation", 0f32),
+                bandwidth: section.get_f32("bandwidth", 1f32),
+            },
+            other => panic!("line {}: unknown disturbance kind \"{}\", expected random_force, random_torque, impulse, step, periodic, random_walk or noise", section.line, other),
+        };
+
+        if let Source::Periodic { period, .. } = source {
+            assert!(period > 0f32, "line {}: period must be positive", section.line);
+        }
+
+        let frame = match section.get_str("frame", "world").as_str() {
+            "world" => Frame::World,
+            "body"  => Frame::Body,
+            other   => panic!("line {}: unknown frame \"{}\", expected world or body", section.line, other),
+        };
+
+        let condition = section.get("when").map(|value| {
+            let words :Vec<&str> = value.split_whitespace().collect();
+            assert!(words.len() == 3, "line {}: when = {} expects <quantity> < | > <value>", section.line, value);
+
+            let quantity = match Quantity::from_name(words[0]) {
+                Some(quantity) => quantity,
+                None => panic!("line {}: unknown quantity \"{}\", expected x, y, z, speed, vertical_speed, angular_speed or tilt", section.line, words[0]),
+            };
+
+            let above = match words[1] {
+                ">" => true,
+                "<" => false,
+                other => panic!("line {}: unknown comparison \"{}\", expected < or >", section.line, other),
+            };
+
+            let threshold = section.parse_numbers("when", words[2], 1)[0];
+
+            Condition {
+                quantity,
+                above,
+                threshold,
+            }
+        });
+
+        let trigger = Trigger {
+            start:     section.get_f32("start", 0f32),
+            end:       section.get_f32("end", f32::INFINITY),
+            condition,
+        };
+
+        Disturbance::new(source).with_trigger(trigger).with_frame(frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPLINE :&str = "
+[bezier]
+p1 = 0 0 0 0
+p2 = 1 0 0 1
+p3 = 2 0 0 2
+p4 = 3 0 0 3
+";
+
+    #[test]
+    fn reads_known_keys () {
+        let scenario = Scenario::parse(format!("[simulation]\nduration = 1\n[object drone]\nrole = drone\nmass = 5\n{}", SPLINE).as_str());
+
+        assert_eq!(scenario.duration, 1f32);
+        assert_eq!(scenario.objects[0].mass, 5f32);
+    }
+
+    #[test]
+    #[should_panic(expected = "line 3: unknown key duraton in [simulation]")]
+    fn rejects_unknown_keys () {
+        Scenario::parse(format!("\n[simulation]\nduraton = 1\n{}", SPLINE).as_str());
+    }
+
+    #[test]
+    #[should_panic(expected = "line 4: unknown key mas in [object]")]
+    fn rejects_keys_of_other_kinds () {
+        Scenario::parse(format!("[object drone]\nrole = drone\nshape = box\nmas = 5\n{}", SPLINE).as_str());
+    }
+
+    #[test]
+    #[should_panic(expected = "line 3: duration is set twice in [simulation]")]
+    fn rejects_repeated_keys () {
+        Scenario::parse(format!("[simulation]\nduration = 1\nduration = 2\n{}", SPLINE).as_str());
+    }
+
+    #[test]
+    #[should_panic(expected = "[simulation] at line 4: duration = soon is not a number")]
+    fn reports_the_line_of_a_bad_value () {
+        Scenario::parse(format!("[simulation]\ndt = 0.01\ngravity = 0 -10 0\nduration = soon\n{}", SPLINE).as_str());
+    }
+
+    #[test]
+    #[should_panic(expected = "[simulation] at line 3: seed = -1 is not an unsigned integer")]
+    fn reports_the_line_of_a_bad_integer () {
+        Scenario::parse(format!("[simulation]\ndt = 0.01\nseed = -1\n{}", SPLINE).as_str());
+    }
+
+    #[test]
+    #[should_panic(expected = "line 3: max_convergence_time needs force_threshold and torque_threshold")]
+    fn convergence_time_needs_thresholds () {
+        Scenario::parse(format!("[evaluation]\nforce_threshold = 2\nmax_convergence_time = 3\n{}", SPLINE).as_str());
+    }
+
+    #[test]
+    fn a_given_beta_is_critically_damped_even_at_its_default () {
+        let mut defaults = DroneController::new();
+        defaults.roll = AxisGains::new(100f32, -25f32);
+
+        let given = Scenario::parse_attitude(&parse_sections("[controller]\nbeta = -25\n")[0], &defaults);
+        assert_eq!(given.roll.alpha, 156.25f32);
+
+        let omitted = Scenario::parse_attitude(&parse_sections("[controller]\n")[0], &defaults);
+        assert_eq!(omitted.roll.alpha, 100f32);
+    }
+
+    #[test]
+    #[should_panic(expected = "no [bezier] section")]
+    fn requires_a_spline () {
+        Scenario::parse("[simulation]\nduration = 1\n");
+    }
+
+    #[test]
+    #[should_panic(expected = "line 1: the first bezier has to start at t = 0, not 1")]
+    fn requires_the_spline_to_start_at_zero () {
+        Scenario::parse("[bezier]\np1 = 0 0 0 1\np2 = 0 0 0 2\np3 = 0 0 0 3\np4 = 0 0 0 4\n");
+    }
+
+    #[test]
+    #[should_panic(expected = "line 7: p1 has to be p4 of the previous bezier")]
+    fn requires_a_continuous_spline () {
+        Scenario::parse(format!("{}[bezier]\np1 = 3 0 0 4\np2 = 3 0 0 5\np3 = 3 0 0 6\np4 = 3 0 0 7\n", SPLINE).as_str());
+    }
+}
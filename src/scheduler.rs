@@ -0,0 +1,243 @@
+use std::collections::VecDeque;
+
+use sensor::SensorKind;
+
+//parts of the simulation loop that can run slower than the physics, the physics itself runs every step
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Task {
+    Reference,          //targets follow the reference spline
+    Position,           //outer control loop, reference -> desired acceleration
+    Attitude,           //inner control loop, desired acceleration -> thrust and torque
+    Sensor(SensorKind), //one sample of a sensor
+}
+
+#[allow(dead_code)]
+impl Task {
+    pub fn all () -> Vec<Task> {
+        let mut tasks = vec![Task::Reference, Task::Position, Task::Attitude];
+        tasks.extend(SensorKind::all().iter().map(|&kind| Task::Sensor(kind)));
+        tasks
+    }
+
+    pub fn name (&self) -> &'static str {
+        match *self {
+            Task::Reference    => "reference",
+            Task::Position     => "position",
+            Task::Attitude     => "attitude",
+            Task::Sensor(kind) => kind.name(),
+        }
+    }
+
+    pub fn from_name (name: &str) -> Option<Task> {
+        Task::all().iter().find(|task| task.name() == name).cloned()
+    }
+}
+
+//when a task runs, in seconds, the scheduler rounds them to whole physics steps
+#[derive(Clone, Copy)]
+pub struct Timing {
+    pub period:  f32, //0 -> every physics step
+    pub phase:   f32, //first run, later runs follow every period
+    pub latency: f32, //time between running and its output being used
+}
+
+#[allow(dead_code)]
+impl Timing {
+    pub fn new (period: f32, phase: f32, latency: f32) -> Timing {
+        Timing {
+            period,
+            phase,
+            latency,
+        }
+    }
+}
+
+//a registered task, everything in physics steps
+#[derive(Clone, Copy)]
+struct Slot {
+    task:    Task,
+    period:  u64,
+    phase:   u64,
+    latency: u64,
+}
+
+//decides which tasks run during a physics step, every task runs every step until it is registered with another timing
+pub struct Scheduler {
+    pub dt: f32, //physics timestep, the base rate
+    slots:  Vec<Slot>,
+}
+
+#[allow(dead_code)]
+impl Scheduler {
+    pub fn new (dt: f32) -> Scheduler {
+        Scheduler {
+            dt,
+            slots: Task::all().iter().map(|&task| Slot { task, period: 1, phase: 0, latency: 0 }).collect(),
+        }
+    }
+
+    //replaces the timing of a task, period, phase and latency have to be multiples of dt
+    pub fn register (&mut self, task: Task, timing: Timing) {
+        let slot = Slot {
+            task,
+            period:  self.to_steps(timing.period, task, "period").max(1),
+            phase:   self.to_steps(timing.phase, task, "phase"),
+            latency: self.to_steps(timing.latency, task, "latency"),
+        };
+
+        let n = self.slots.iter().position(|slot| slot.task == task).unwrap();
+        self.slots[n] = slot;
+    }
+
+    //does the task run during physics step number step (counting from 0)
+    pub fn due (&self, task: Task, step: u64) -> bool {
+        let slot = self.slot(task);
+        //period 0 runs every step like 1, register never stores it but the modulo must not see it
+        step >= slot.phase && (slot.period == 0 || (step - slot.phase) % slot.period == 0)
+    }
+
+    //step at which the output of a run during step gets used
+    pub fn ready_step (&self, task: Task, step: u64) -> u64 {
+        step + self.slot(task).latency
+    }
+
+    pub fn timing (&self, task: Task) -> Timing {
+        let slot = self.slot(task);
+
+        Timing {
+            period:  slot.period as f32 * self.dt,
+            phase:   slot.phase as f32 * self.dt,
+            latency: slot.latency as f32 * self.dt,
+        }
+    }
+
+    pub fn period (&self, task: Task) -> f32 {
+        self.timing(task).period
+    }
+
+    fn slot (&self, task: Task) -> Slot {
+        *self.slots.iter().find(|slot| slot.task == task).unwrap()
+    }
+
+    fn to_steps (&self, seconds: f32, task: Task, what: &str) -> u64 {
+        assert!(seconds >= 0f32, "{} {} can not be negative", task.name(), what);
+
+        let steps = (seconds / self.dt).round();
+        assert!((steps * self.dt - seconds).abs() <= 1e-3f32 * self.dt, "{} {} {} is not a multiple of dt {}", task.name(), what, seconds, self.dt);
+
+        steps as u64
+    }
+}
+
+//output of a task on its way to the consumer, the consumer sees the newest value that is ready and holds it in between
+pub struct Delayed<T> {
+    current: T,
+    pending: VecDeque<(u64, T)>, //ready step, value, in order
+}
+
+#[allow(dead_code)]
+impl<T: Copy> Delayed<T> {
+    //initial is what the consumer sees until the first output is ready
+    pub fn new (initial: T) -> Delayed<T> {
+        Delayed {
+            current: initial,
+            pending: VecDeque::new(),
+        }
+    }
+
+    pub fn push (&mut self, ready_step: u64, value: T) {
+        self.pending.push_back((ready_step, value));
+    }
+
+    //the newest value that became ready by step since the last call, None if nothing new arrived
+    pub fn take (&mut self, step: u64) -> Option<T> {
+        let mut arrived = None;
+
+        while let Some(&(ready_step, value)) = self.pending.front() {
+            if ready_step > step {
+                break;
+            }
+
+            self.current = value;
+            self.pending.pop_front();
+            arrived = Some(value);
+        }
+
+        arrived
+    }
+
+    //the value to use during step
+    pub fn get (&mut self, step: u64) -> T {
+        self.take(step);
+        self.current
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DT :f32 = 0.01f32;
+
+    fn due_steps (scheduler: &Scheduler, task: Task, steps: u64) -> Vec<u64> {
+        (0 .. steps).filter(|&step| scheduler.due(task, step)).collect()
+    }
+
+    #[test]
+    fn tasks_run_every_step_by_default () {
+        let scheduler = Scheduler::new(DT);
+
+        for task in Task::all() {
+            assert_eq!(due_steps(&scheduler, task, 5), vec![0, 1, 2, 3, 4], "{}", task.name());
+            assert_eq!(scheduler.ready_step(task, 3), 3);
+        }
+    }
+
+    #[test]
+    fn period_and_phase_pick_the_steps () {
+        let mut scheduler = Scheduler::new(DT);
+        scheduler.register(Task::Position, Timing::new(0.04f32, 0.03f32, 0f32));
+        scheduler.register(Task::Attitude, Timing::new(0f32, 0.02f32, 0f32));
+
+        assert_eq!(due_steps(&scheduler, Task::Position, 16), vec![3, 7, 11, 15]);
+        assert_eq!(due_steps(&scheduler, Task::Attitude, 5), vec![2, 3, 4]);
+        assert_eq!(due_steps(&scheduler, Task::Reference, 3), vec![0, 1, 2]);
+    }
+
+    #[test]
+    #[should_panic(expected = "is not a multiple of dt")]
+    fn periods_have_to_be_whole_steps () {
+        Scheduler::new(DT).register(Task::Position, Timing::new(0.015f32, 0f32, 0f32));
+    }
+
+    #[test]
+    fn outputs_arrive_after_the_latency () {
+        let mut scheduler = Scheduler::new(DT);
+        scheduler.register(Task::Attitude, Timing::new(0f32, 0f32, 0.03f32));
+
+        let mut delayed = Delayed::new(-1i32);
+
+        for step in 0 .. 6 {
+            delayed.push(scheduler.ready_step(Task::Attitude, step), step as i32);
+
+            //each output is used exactly 3 steps after it was computed, the initial value until the first one
+            let expected = if step >= 3 { step as i32 - 3 } else { -1 };
+            assert_eq!(delayed.get(step), expected);
+        }
+    }
+
+    #[test]
+    fn take_only_reports_new_values () {
+        let mut delayed = Delayed::new(0f32);
+        delayed.push(2, 1f32);
+        delayed.push(2, 2f32);
+        delayed.push(4, 3f32);
+
+        assert_eq!(delayed.take(1), None);
+        assert_eq!(delayed.take(2), Some(2f32));
+        assert_eq!(delayed.take(3), None);
+        assert_eq!(delayed.get(3), 2f32);
+        assert_eq!(delayed.get(5), 3f32);
+        assert_eq!(delayed.take(5), None);
+    }
+}
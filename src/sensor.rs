@@ -0,0 +1,228 @@
+use vector::Vector;
+use object::Object;
+use random::Random;
+use scheduler::Scheduler;
+use scheduler::Task;
+use scheduler::Delayed;
+
+pub const DEFAULT_MAGNETIC_FIELD :Vector = Vector { x: 0.2f32, y: -0.4f32, z: 0f32, w: 0f32 }; //gauss, north is world x, pointing down like in the northern hemisphere
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SensorKind {
+    Accelerometer, //specific force (acceleration without gravity), body frame
+    Gyroscope,     //angular velocity, body frame
+    Barometer,     //altitude along world y, scalar, only x is used
+    GpsPosition,   //world frame
+    GpsVelocity,   //world frame
+    Magnetometer,  //earth magnetic field, body frame
+}
+
+#[allow(dead_code)]
+impl SensorKind {
+    pub fn all () -> [SensorKind; 6] {
+        [SensorKind::Accelerometer, SensorKind::Gyroscope, SensorKind::Barometer, SensorKind::GpsPosition, SensorKind::GpsVelocity, SensorKind::Magnetometer]
+    }
+
+    pub fn name (&self) -> &'static str {
+        match *self {
+            SensorKind::Accelerometer => "accelerometer",
+            SensorKind::Gyroscope     => "gyroscope",
+            SensorKind::Barometer     => "barometer",
+            SensorKind::GpsPosition   => "gps_position",
+            SensorKind::GpsVelocity   => "gps_velocity",
+            SensorKind::Magnetometer  => "magnetometer",
+        }
+    }
+
+    pub fn from_name (name: &str) -> Option<SensorKind> {
+        SensorKind::all().iter().find(|kind| kind.name() == name).cloned()
+    }
+
+    pub fn axes (&self) -> usize {
+        match *self {
+            SensorKind::Barometer => 1,
+            _ => 3,
+        }
+    }
+
+    //what a perfect sensor reads, acceleration is the total acceleration of the object including gravity
+    pub fn truth (&self, object: &Object, acceleration: Vector, gravity: Vector, magnetic_field: Vector) -> Vector {
+        let world_to_local = object.rotation.to_matrix().transpose();
+
+        match *self {
+            SensorKind::Accelerometer => world_to_local * (acceleration - gravity),
+            SensorKind::Gyroscope     => world_to_local * object.angular_velocity,
+            SensorKind::Barometer     => Vector::new(object.position.y, 0f32, 0f32, 0f32),
+            SensorKind::GpsPosition   => object.position,
+            SensorKind::GpsVelocity   => object.velocity,
+            SensorKind::Magnetometer  => world_to_local * magnetic_field,
+        }
+    }
+}
+
+//errors of a sensor, all per axis, the defaults make a perfect sensor
+#[derive(Clone, Copy)]
+pub struct NoiseModel {
+    pub noise:        f32, //standard deviation of the white noise on every sample
+    pub bias:         f32, //standard deviation of the constant offset drawn at startup
+    pub bias_walk:    f32, //random walk of the offset, standard deviation after one second
+    pub quantization: f32, //resolution of the output, 0 -> continuous
+}
+
+#[allow(dead_code)]
+impl NoiseModel {
+    pub fn new () -> NoiseModel {
+        NoiseModel {
+            noise:        0f32,
+            bias:         0f32,
+            bias_walk:    0f32,
+            quantization: 0f32,
+        }
+    }
+}
+
+impl Default for NoiseModel {
+    fn default () -> NoiseModel {
+        NoiseModel::new()
+    }
+}
+
+pub struct Sensor {
+    pub kind:  SensorKind,
+    pub model: NoiseModel,
+    pub bias:  Vector, //current offset
+    output:    Delayed<Vector>, //samples on their way to the flight controller
+}
+
+#[allow(dead_code)]
+impl Sensor {
+    pub fn new (kind: SensorKind, model: NoiseModel, rng: &mut Random) -> Sensor {
+        let mut bias = Vector::null();
+        for n in 0 .. kind.axes() {
+            bias[n] = model.bias * rng.gaussian();
+        }
+
+        Sensor {
+            kind,
+            model,
+            bias,
+            output: Delayed::new(Vector::null()),
+        }
+    }
+
+    //truth with bias, noise and quantization, dt is the time since the last sample for the bias walk
+    pub fn measure (&mut self, truth: Vector, dt: f32, rng: &mut Random) -> Vector {
+        let mut reading = truth;
+
+        for n in 0 .. self.kind.axes() {
+            self.bias[n] += self.model.bias_walk * dt.sqrt() * rng.gaussian();
+
+            let mut value = truth[n] + self.bias[n] + self.model.noise * rng.gaussian();
+
+            if self.model.quantization > 0f32 {
+                value = (value / self.model.quantization).round() * self.model.quantization;
+            }
+
+            reading[n] = value;
+        }
+
+        reading
+    }
+}
+
+//newest sample of every sensor the flight controller can use, zero until the first one arrived
+#[derive(Clone, Copy)]
+pub struct Readings {
+    pub accelerometer: Vector,
+    pub gyroscope:     Vector,
+    pub barometer:     f32,
+    pub gps_position:  Vector,
+    pub gps_velocity:  Vector,
+    pub magnetometer:  Vector,
+}
+
+#[allow(dead_code)]
+impl Readings {
+    pub fn new () -> Readings {
+        Readings {
+            accelerometer: Vector::null(),
+            gyroscope:     Vector::null(),
+            barometer:     0f32,
+            gps_position:  Vector::new(0f32, 0f32, 0f32, 1f32),
+            gps_velocity:  Vector::null(),
+            magnetometer:  Vector::null(),
+        }
+    }
+
+    pub fn set (&mut self, kind: SensorKind, reading: Vector) {
+        match kind {
+            SensorKind::Accelerometer => self.accelerometer = reading,
+            SensorKind::Gyroscope     => self.gyroscope     = reading,
+            SensorKind::Barometer     => self.barometer     = reading.x,
+            SensorKind::GpsPosition   => self.gps_position  = reading,
+            SensorKind::GpsVelocity   => self.gps_velocity  = reading,
+            SensorKind::Magnetometer  => self.magnetometer  = reading,
+        }
+    }
+}
+
+impl Default for Readings {
+    fn default () -> Readings {
+        Readings::new()
+    }
+}
+
+//the sensors of the drone, each one samples when the scheduler runs its task
+pub struct Sensors {
+    pub sensors:        Vec<Sensor>,
+    pub magnetic_field: Vector, //world frame
+    rng:                Random, //own stream, adding sensor noise doesn't change the disturbances of a seed
+}
+
+#[allow(dead_code)]
+impl Sensors {
+    //every kind of sensor, perfect unless models has an entry for it
+    pub fn new (models: &[(SensorKind, NoiseModel)], magnetic_field: Vector, mut rng: Random) -> Sensors {
+        let sensors = SensorKind::all().iter().map(|&kind| {
+            let model = match models.iter().find(|entry| entry.0 == kind) {
+                Some(entry) => entry.1,
+                None => NoiseModel::new(),
+            };
+
+            Sensor::new(kind, model, &mut rng)
+        }).collect();
+
+        Sensors {
+            sensors,
+            magnetic_field,
+            rng,
+        }
+    }
+
+    //samples the sensors that are due during step, acceleration is the total acceleration of the object during the step
+    pub fn sample (&mut self, scheduler: &Scheduler, step: u64, object: &Object, acceleration: Vector, gravity: Vector) {
+        for sensor in self.sensors.iter_mut() {
+            let task = Task::Sensor(sensor.kind);
+
+            if scheduler.due(task, step) {
+                let truth = sensor.kind.truth(object, acceleration, gravity, self.magnetic_field);
+                let reading = sensor.measure(truth, scheduler.period(task), &mut self.rng);
+
+                sensor.output.push(scheduler.ready_step(task, step), reading);
+            }
+        }
+    }
+
+    //samples that arrived by step since the last call, the newest one per sensor
+    pub fn arrivals (&mut self, step: u64) -> Vec<(SensorKind, Vector)> {
+        let mut arrivals = Vec::<(SensorKind, Vector)>::new();
+
+        for sensor in self.sensors.iter_mut() {
+            if let Some(reading) = sensor.output.take(step) {
+                arrivals.push((sensor.kind, reading));
+            }
+        }
+
+        arrivals
+    }
+}
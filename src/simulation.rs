@@ -0,0 +1,367 @@
+use object::Object;
+use objectmanager::ObjectManager;
+use objectmanager::ObjectTag;
+use spline::Spline;
+use vector::Vector;
+use quaternion::Quaternion;
+use scenario::Scenario;
+use scenario::Role;
+use disturbance::Disturbance;
+use aerodynamics::Drag;
+use aerodynamics::Wind;
+use collision::Contact;
+use random::Random;
+use recorder::Record;
+use drone;
+use drone::Airframe;
+use drone::Wrench;
+use motor::Motor;
+use controller::FlightController;
+use controller::Command;
+use scheduler::Scheduler;
+use scheduler::Task;
+use scheduler::Delayed;
+use sensor::Sensors;
+use sensor::Readings;
+use sensor::SensorKind;
+use estimator::Estimator;
+use estimator::Estimate;
+
+const SENSOR_STREAM :u64 = 0x5E45_0125; //mixed into the seed for the sensor noise
+const WIND_STREAM   :u64 = 0x0A1B_2C3D; //mixed into the seed for the turbulence
+
+//owns the world state and advances it, does not know anything about rendering
+pub struct Simulation {
+    pub object_manager: ObjectManager,
+    pub drone:          ObjectTag,
+    pub airframe:       Airframe,
+    pub motors:         Vec<Motor>, //rotor state of the drone, one per airframe rotor
+    pub followers:      Vec<(ObjectTag, f32)>, //objects following the reference spline, with their lead time
+    pub disturbances:   Vec<(ObjectTag, Disturbance)>,
+    pub wind:           Wind,
+    pub drag:           Vec<(ObjectTag, Drag)>, //objects the air acts on
+    pub names:          Vec<(String, ObjectTag)>, //from the scenario
+    pub near_miss:      f32, //shapes closer than this show up in contacts
+    pub contacts:       Vec<Contact>, //of the state the last step started from
+    pub collisions:     Vec<Contact>, //contacts that started touching during the last step
+    pub spline:         Spline,
+    pub dt:             f32, //physics timestep size
+    pub scheduler:      Scheduler, //when the reference and the control loops run
+    pub gravity:        Vector,
+    pub controller:     Box<dyn FlightController>, //flies the drone, can be swapped between steps
+    pub rng:            Random, //all randomness in the simulation comes from here, the sensors use a stream derived from its seed
+    pub sensors:        Sensors, //of the drone
+    pub readings:       Readings, //newest sensor samples available to the flight controller
+    pub estimator:      Option<Box<dyn Estimator>>, //None -> the controller sees the true state
+    arrivals:           Vec<(SensorKind, Vector)>, //samples that arrived during the last step, for the estimator
+    pub t:              f32, //current time
+    pub steps:          u64, //physics steps taken so far
+    acceleration:       Delayed<Vector>, //position loop output, held between runs
+    wrench:             Delayed<Wrench>, //attitude loop output, held between runs
+}
+
+#[allow(dead_code)]
+impl Simulation {
+    pub fn new (scenario: &Scenario) -> Simulation {
+        let mut object_manager = ObjectManager::new();
+        object_manager.integrator     = scenario.integrator;
+        object_manager.ground         = scenario.ground;
+        object_manager.ground_surface = scenario.ground_surface;
+        object_manager.response       = scenario.response;
+
+        let mut drone     = Option::None::<ObjectTag>;
+        let mut followers = Vec::<(ObjectTag, f32)>::new();
+        let mut names     = Vec::<(String, ObjectTag)>::new();
+        let mut drag      = Vec::<(ObjectTag, Drag)>::new();
+
+        for description in &scenario.objects {
+            let mut new_object = Object::new();
+            new_object.model            = description.model.clone();
+            new_object.scale            = description.scale;
+            new_object.mass             = description.mass;
+            new_object.inertia          = description.inertia;
+            new_object.position         = description.position;
+            new_object.velocity         = description.velocity;
+            new_object.rotation         = Quaternion::from_vector(description.rotation);
+            new_object.angular_velocity = description.angular_velocity;
+
+            if description.start_on_reference {
+                let (p, v, _) = scenario.spline.sample_all(0f32);
+                new_object.position = p;
+                new_object.velocity = v;
+            }
+
+            let tag = object_manager.push_object_tagged(new_object);
+            object_manager.set_shape(description.shape.clone(), &tag);
+            object_manager.set_material(description.material, &tag);
+
+            match description.role {
+                Role::Drone => {
+                    assert!(drone.is_none(), "scenario contains more than one drone");
+                    drone = Some(tag);
+                },
+                Role::Target(lead) => followers.push((tag, lead)),
+                Role::Static => {},
+            }
+
+            if !description.drag.is_zero() {
+                drag.push((tag, description.drag));
+            }
+
+            names.push((description.name.clone(), tag));
+        }
+
+        let mut disturbances = Vec::<(ObjectTag, Disturbance)>::new();
+
+        for (name, disturbance) in &scenario.disturbances {
+            let tag = match names.iter().find(|entry| &entry.0 == name) {
+                Some(entry) => entry.1,
+                None => panic!("disturbance acts on unknown object {}", name),
+            };
+
+            disturbances.push((tag, disturbance.clone()));
+        }
+
+        let drone = drone.expect("scenario contains no drone");
+
+        //start at hover rpm, otherwise slow motors drop the drone during the first steps
+        let mut motors = scenario.airframe.motors();
+        {
+            let hover_thrust = Vector::magnitude(scenario.gravity) * object_manager.get_object(&drone).mass / motors.len() as f32;
+
+            for motor in motors.iter_mut() {
+                motor.rpm = motor.model.rpm_for_thrust(hover_thrust).min(motor.model.max_rpm);
+            }
+        }
+
+        let mut scheduler = Scheduler::new(scenario.dt);
+
+        for &(task, timing) in &scenario.schedule {
+            scheduler.register(task, timing);
+        }
+
+        //hover until the control loops produced their first output
+        let hover = Wrench {
+            thrust: Vector::magnitude(scenario.gravity) * object_manager.get_object(&drone).mass,
+            torque: Vector::null(),
+        };
+
+        let rng = match scenario.seed {
+            Some(seed) => Random::new(seed),
+            None => Random::from_entropy(),
+        };
+
+        let sensors = Sensors::new(&scenario.sensors, scenario.magnetic_field, Random::new(rng.seed() ^ SENSOR_STREAM));
+        let wind    = Wind::new(scenario.wind, scenario.gusts.clone(), scenario.turbulence, Random::new(rng.seed() ^ WIND_STREAM));
+
+        let mut estimator = scenario.build_estimator(&sensors, &scheduler);
+        if let Some(ref mut estimator) = estimator {
+            estimator.reset(object_manager.get_object(&drone));
+        }
+
+        let contacts = object_manager.contacts(scenario.near_miss); //starting on the ground isn't a collision
+
+        Simulation {
+            object_manager,
+            drone,
+            airframe:       scenario.airframe.clone(),
+            motors,
+            followers,
+            disturbances,
+            wind,
+            drag,
+            names,
+            near_miss:      scenario.near_miss,
+            contacts,
+            collisions:     Vec::<Contact>::new(),
+            spline:         scenario.spline.clone(),
+            dt:             scenario.dt,
+            scheduler,
+            gravity:        scenario.gravity,
+            controller:     scenario.build_controller(),
+            rng,
+            sensors,
+            readings:       Readings::new(),
+            estimator,
+            arrivals:       Vec::<(SensorKind, Vector)>::new(),
+            t:              0f32,
+            steps:          0,
+            acceleration:   Delayed::new(Vector::null()),
+            wrench:         Delayed::new(hover),
+        }
+    }
+
+    pub fn step (&mut self) -> Record {
+        self.t += self.dt;
+        let t = self.t;
+
+        let step = self.steps;
+        self.steps += 1;
+
+        if self.scheduler.due(Task::Reference, step) {
+            for &(ref tag, lead) in &self.followers { //update targets
+                let (p, _, _) = self.spline.sample_all((t + lead) % self.spline.duration());
+                let obj = self.object_manager.get_mut_object(tag);
+                obj.position = p;
+            }
+        }
+
+        //collisions of the state the step starts from, the ones that touched before aren't new
+        {
+            let contacts = self.object_manager.contacts(self.near_miss);
+
+            self.collisions = contacts.iter().filter(|contact| {
+                contact.is_touching() && !self.contacts.iter().any(|previous| previous.is_touching() && previous.same_pair(contact))
+            }).cloned().collect();
+
+            self.contacts = contacts;
+        }
+
+        let gravity = self.gravity;
+
+        //what the controller gets to see, the true state unless there is an estimator
+        let estimate = match self.estimator {
+            Some(ref mut estimator) => {
+                estimator.update(&self.readings, &self.arrivals, self.dt);
+                estimator.estimate()
+            },
+            None => Estimate::from_object(self.object_manager.get_object(&self.drone)),
+        };
+
+        let perceived = estimate.apply(self.object_manager.get_object(&self.drone));
+
+        if self.scheduler.due(Task::Position, step) {
+            let acceleration = self.controller.update_position(
+                &perceived,
+                &self.spline,
+                t,
+                self.scheduler.period(Task::Position)
+            );
+
+            self.acceleration.push(self.scheduler.ready_step(Task::Position, step), acceleration);
+        }
+
+        let acceleration = self.acceleration.get(step);
+
+        if self.scheduler.due(Task::Attitude, step) {
+            let heading = self.spline.sample_heading(t % self.spline.duration());
+
+            let wrench = self.controller.update_attitude(
+                &perceived,
+                acceleration - gravity,
+                heading,
+                self.scheduler.period(Task::Attitude)
+            );
+
+            self.wrench.push(self.scheduler.ready_step(Task::Attitude, step), wrench);
+        }
+
+        let command = Command {
+            acceleration,
+            wrench:       self.wrench.get(step),
+        };
+
+        let allocation = self.airframe.allocate(command.wrench);
+        let motor = self.airframe.motor;
+        let pwm_raw :Vec<f32> = allocation.unconstrained.iter().map(|&thrust| motor.pwm_for_thrust(thrust)).collect();
+        let pwm     :Vec<f32> = allocation.thrust.iter().map(|&thrust| motor.pwm_for_thrust(thrust).max(motor.idle_pwm).min(1f32)).collect();
+
+        for (motor, &pwm) in self.motors.iter_mut().zip(pwm.iter()) {
+            motor.update(pwm, self.dt);
+        }
+
+        drone::apply_motors(&mut self.object_manager, &self.drone, &self.airframe, &self.motors);
+        self.object_manager.apply_force(gravity, &self.drone);
+
+        self.wind.update(self.dt);
+        let wind = self.wind.velocity(t);
+
+        let mut drag_force  = Vector::null();
+        let mut drag_torque = Vector::null();
+
+        for (tag, drag) in &self.drag {
+            let (force, torque) = drag.apply(&mut self.object_manager, tag, wind);
+
+            if *tag == self.drone {
+                drag_force  = force;
+                drag_torque = torque;
+            }
+        }
+
+        let mut disturbance_force  = Vector::null();
+        let mut disturbance_torque = Vector::null();
+
+        for &mut (ref tag, ref mut disturbance) in self.disturbances.iter_mut() {
+            let (force, torque) = disturbance.apply(&mut self.object_manager, tag, t, self.dt, &mut self.rng);
+
+            if *tag == self.drone {
+                disturbance_force  += force;
+                disturbance_torque += torque;
+            }
+        }
+
+        let record = {
+            let obj = self.object_manager.get_object(&self.drone);
+            let t_ref = t % self.spline.duration();
+            let (p_ref, v_ref, a_ref) = self.spline.sample_all(t_ref);
+
+            Record {
+                t,
+                position:               obj.position,
+                velocity:               obj.velocity,
+                rotation:               obj.rotation,
+                angular_velocity:       obj.angular_velocity,
+                reference_position:     p_ref,
+                reference_velocity:     v_ref,
+                reference_acceleration: a_ref,
+                reference_heading:      self.spline.sample_heading(t_ref),
+                commanded_acceleration: command.acceleration,
+                commanded_wrench:       command.wrench,
+                allocated_wrench:       allocation.wrench,
+                saturated:              allocation.saturated,
+                pwm_raw,
+                pwm,
+                disturbance_force,
+                disturbance_torque,
+                readings:               self.readings, //what the controller had during the step, measured at its start
+                estimate,
+                wind,
+                drag_force,
+                drag_torque,
+                clearance:              self.clearance(&self.drone),
+                collision:              self.contacts.iter().any(|contact| contact.involves(&self.drone) && contact.is_touching()),
+            }
+        };
+
+        let mass = self.object_manager.get_object(&self.drone).mass;
+        let force = self.object_manager.get_force(&self.drone);
+
+        self.object_manager.update_physics(self.dt);
+
+        //contacts push within the step, e.g. the ground holding up a landed drone
+        let acceleration = (force + self.object_manager.get_contact_impulse(&self.drone) / self.dt) / mass;
+
+        //the sensors see the state at the end of the step and the acceleration during it,
+        //the controller gets their readings from the next step on
+        self.sensors.sample(&self.scheduler, step, self.object_manager.get_object(&self.drone), acceleration, gravity);
+        self.arrivals = self.sensors.arrivals(step);
+
+        for &(kind, reading) in &self.arrivals {
+            self.readings.set(kind, reading);
+        }
+
+        record
+    }
+
+    //distance of the object to the closest shape or the ground, negative while they overlap, infinity if nothing is within the near miss distance
+    pub fn clearance (&self, tag: &ObjectTag) -> f32 {
+        self.contacts.iter().filter(|contact| contact.involves(tag)).map(|contact| -contact.depth).fold(f32::INFINITY, f32::min)
+    }
+
+    pub fn name (&self, tag: &ObjectTag) -> &str {
+        match self.names.iter().find(|entry| entry.1 == *tag) {
+            Some(entry) => entry.0.as_str(),
+            None => "unnamed",
+        }
+    }
+}
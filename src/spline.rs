@@ -1,10 +1,12 @@
 use vector::Vector;
 
+#[derive(Clone)]
 pub struct Bezier {
     p1: Vector,
     p2: Vector,
     p3: Vector,
     p4: Vector,
+    heading: Option<[f32; 4]>, //optional heading (yaw) control values, shares tau with the position
 }
 
 impl Bezier {
@@ -15,13 +17,39 @@ impl Bezier {
         assert!(p3.w < p4.w);
 
         Bezier {
-            p1: p1,
-            p2: p2,
-            p3: p3,
-            p4: p4,
+            p1,
+            p2,
+            p3,
+            p4,
+            heading: None,
         }
     }
 
+    //adds a heading profile, h1 .. h4 are bezier control values in radians
+    #[allow(dead_code)]
+    pub fn with_heading (mut self, h1: f32, h2: f32, h3: f32, h4: f32) -> Bezier {
+        self.heading = Some([h1, h2, h3, h4]);
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn sample_heading (&self, tau: f32) -> Option<(f32, f32)> { //heading and its derivative with respect to tau
+        assert!(0f32 <= tau);
+        assert!(tau <= 1f32);
+
+        self.heading.map(|h| (
+            h[0] * ((1f32 - tau) * (1f32 - tau) * (1f32 - tau)) +
+            h[1] * (3f32 * (1f32 - tau) * (1f32 - tau) * tau) +
+            h[2] * (3f32 * (1f32 - tau) * tau * tau) +
+            h[3] * (tau * tau * tau),
+
+            h[0] * (-3f32 * (1f32 - tau) * (1f32 - tau)) +
+            h[1] * (9f32 * tau * tau - 12f32 * tau + 3f32) +
+            h[2] * (-9f32 * tau * tau + 6f32 * tau) +
+            h[3] * (3f32 * tau * tau)
+        ))
+    }
+
     #[allow(dead_code)]
     pub fn sample_p (&self, tau: f32) -> Vector {
         assert!(0f32 <= tau);
@@ -205,6 +233,7 @@ impl Bezier {
     }
 }
 
+#[derive(Clone)]
 pub struct Spline {
     curve_list: Vec<Bezier>,
 }
@@ -219,7 +248,7 @@ impl Spline {
 
     #[allow(dead_code)]
     pub fn push_curve (&mut self, new_curve :Bezier) {
-        if self.curve_list.len() > 0 {
+        if !self.curve_list.is_empty() {
             let n = self.curve_list.len() - 1;
             let last_curve = &self.curve_list[n];
 
@@ -230,6 +259,10 @@ impl Spline {
             assert!(last_state.y == start_state.y);
             assert!(last_state.z == start_state.z);
             assert!(last_state.w == start_state.w);
+
+            if let (Some(last_heading), Some(start_heading)) = (last_curve.heading, new_curve.heading) {
+                assert!(last_heading[3] == start_heading[0]);
+            }
         }
         
         self.curve_list.push(new_curve);
@@ -262,7 +295,7 @@ impl Spline {
     pub fn sample (&self, t :f32) -> Vector {
         let (n, tau) = self.find_n_tau(t);
 
-        let p = self.curve_list[n as usize].sample_p(tau);
+        let p = self.curve_list[n].sample_p(tau);
         
         p.to_position()
     }
@@ -298,6 +331,16 @@ impl Spline {
         ddp_dt2.to_translation()
     }
 
+    //heading and heading rate, None if the curve at t has no heading profile
+    #[allow(dead_code)]
+    pub fn sample_heading (&self, t: f32) -> Option<(f32, f32)> {
+        let (n, tau) = self.find_n_tau(t);
+
+        let dt_dtau = self.curve_list[n].sample_dp_dtau(tau).w; //first derivative of t with respect to tau
+
+        self.curve_list[n].sample_heading(tau).map(|(h, dh_dtau)| (h, dh_dtau / dt_dtau))
+    }
+
     #[allow(dead_code)]
     pub fn sample_all (&self, t: f32) -> (Vector, Vector, Vector) {
         let (n, tau) = self.find_n_tau(t);
@@ -320,6 +363,12 @@ impl Spline {
     }
 }
 
+impl Default for Spline {
+    fn default () -> Spline {
+        Spline::new()
+    }
+}
+
 #[allow(dead_code)]
 pub fn lissajous () -> Spline {
     let mut spline = Spline::new();
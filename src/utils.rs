@@ -1,7 +1,6 @@
 use std::path::Path;
 use std::fs::File;
 use std::io::Read;
-use std::error::Error;
 use std::str;
 
 #[allow(dead_code)]
@@ -24,9 +23,9 @@ pub fn read_file(filename :&str) -> String {
     let path = Path::new(filename);
     let display = path.display();
 
-    let mut file = match File::open(&path) {
+    let mut file = match File::open(path) {
         Err(why) => {
-            println!("couldn't open {}: {}", display, why.description());
+            println!("couldn't open {}: {}", display, why);
             panic!();
         },
         Ok(file) => file,
@@ -34,13 +33,10 @@ pub fn read_file(filename :&str) -> String {
 
     let mut file_data = String::new();
 
-    match file.read_to_string(&mut file_data) {
-        Err(why) => {
-            println!("couldn't read {}: {}", display, why.description());
-            panic!();
-        },
-        Ok(_) => {},
-    };
+    if let Err(why) = file.read_to_string(&mut file_data) {
+        println!("couldn't read {}: {}", display, why);
+        panic!();
+    }
 
     file_data
 }
@@ -1,7 +1,7 @@
 use std::ops::*;
 use std;
-use rand;
-use rand::Rng;
+
+use random::Random;
 
 #[derive(Clone, Copy)]
 pub struct Vector {
@@ -15,10 +15,10 @@ pub struct Vector {
 impl Vector {
     pub fn new (x :f32, y :f32, z :f32, w :f32) -> Vector {
         Vector {
-            x: x,
-            y: y,
-            z: z,
-            w: w,
+            x,
+            y,
+            z,
+            w,
         }
     }
 
@@ -128,9 +128,9 @@ impl Vector {
         let len = Vector::magnitude(v);
 
         if len == 0.0f32 {
-            return None;
+            None
         } else {
-            return Some(v / len);
+            Some(v / len)
         }
     }
 
@@ -138,21 +138,16 @@ impl Vector {
         Vector::dot(
             Vector::normalize(v1).expect("cos_angle with v1 = Vector::Null()"),
             Vector::normalize(v2).expect("cos_angle with v2 = Vector::Null()")
-        ).min(1f32).max(-1f32)
+        ).clamp(-1f32, 1f32)
     }
 
     pub fn angle (v1 :Vector, v2 :Vector) -> f32 {
-        let cos_angle = Vector::cos_angle(v1, v2);
-        let angle = cos_angle.acos();
-
-        angle
+        Vector::cos_angle(v1, v2).acos()
     }
     
-    pub fn random_unitvector () -> Vector {
-        let mut rng = rand::thread_rng();
-        
-        let phi :f32 = rng.gen_range::<f32>(0f32, 2f32 * std::f32::consts::PI);
-        let u   :f32 = rng.gen_range::<f32>(-1f32, 1f32);
+    pub fn random_unitvector (rng :&mut Random) -> Vector {
+        let phi :f32 = rng.gen_range(0f32, 2f32 * std::f32::consts::PI);
+        let u   :f32 = rng.gen_range(-1f32, 1f32);
         let r   :f32 = f32::sqrt(1f32 - u*u);
         
         Vector {
@@ -165,16 +160,14 @@ impl Vector {
     
     //returns a unit vector which has an angle theta relative to v, where theta_low < theta < theta_high
     //assumes small values for theta_high
-    pub fn offset (v :Vector, theta_low :f32, theta_high :f32) -> Vector {
+    pub fn offset (v :Vector, theta_low :f32, theta_high :f32, rng :&mut Random) -> Vector {
         assert!(0.0f32 <= theta_low);
         assert!(theta_low < theta_high);
         
-        let mut rng = rand::thread_rng();
-        
-        let mut v2 = Vector::cross(Vector::random_unitvector(), v);
+        let mut v2 = Vector::cross(Vector::random_unitvector(rng), v);
         v2 = Vector::normalize(v2).unwrap();
 
-        let result = Vector::normalize(v).unwrap() + v2 * rng.gen_range::<f32>(theta_low, theta_high);
+        let result = Vector::normalize(v).unwrap() + v2 * rng.gen_range(theta_low, theta_high);
         
         Vector::normalize(result).unwrap()
     }
@@ -268,3 +261,30 @@ impl Div<f32> for Vector {
         result
     }
 }
+
+//0 - x, 1 - y, 2 - z, 3 - w
+impl Index<usize> for Vector {
+    type Output = f32;
+
+    fn index (&self, index :usize) -> &f32 {
+        match index {
+            0 => &self.x,
+            1 => &self.y,
+            2 => &self.z,
+            3 => &self.w,
+            _ => panic!("vector index {} out of range", index),
+        }
+    }
+}
+
+impl IndexMut<usize> for Vector {
+    fn index_mut (&mut self, index :usize) -> &mut f32 {
+        match index {
+            0 => &mut self.x,
+            1 => &mut self.y,
+            2 => &mut self.z,
+            3 => &mut self.w,
+            _ => panic!("vector index {} out of range", index),
+        }
+    }
+}